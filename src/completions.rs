@@ -0,0 +1,241 @@
+//! Shell completion scripts, printed by the hidden `completions <shell>`
+//! subcommand.
+//!
+//! All three scripts are generated from `FLAGS` and `SUBCOMMANDS`, which
+//! have to be kept in step with `parse_args` by hand.
+
+use std::fmt::Write;
+use std::io;
+
+use crate::error;
+
+/// A shell there is a completion script for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub(crate) fn parse(value: &str) -> io::Result<Self> {
+        match value {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(error(&format!(
+                "unsupported shell '{value}'; expected bash, zsh, or fish"
+            ))),
+        }
+    }
+}
+
+/// What follows a flag.
+enum Value {
+    /// Nothing; the flag stands alone, or only takes a value after `=`.
+    None,
+    /// A value there's no point guessing at, like a pattern or a count.
+    Any,
+    File,
+    Choices(&'static [&'static str]),
+}
+
+struct Flag {
+    long: &'static str,
+    short: Option<char>,
+    value: Value,
+    description: &'static str,
+}
+
+const fn flag(long: &'static str, description: &'static str) -> Flag {
+    Flag { long, short: None, value: Value::None, description }
+}
+
+const fn takes(long: &'static str, value: Value, description: &'static str) -> Flag {
+    Flag { long, short: None, value, description }
+}
+
+const fn short(short: char, flag: Flag) -> Flag {
+    Flag { short: Some(short), ..flag }
+}
+
+/// Every flag `parse_args` takes. Descriptions avoid quotes, brackets and
+/// colons, which the scripts would otherwise need to escape.
+const FLAGS: &[Flag] = &[
+    flag("line-input", "read each answer as a line instead of a keypress"),
+    flag("stat", "show a diffstat instead of the patch"),
+    flag("names", "show only the names of changed files"),
+    flag("no-shortstat", "leave the size out of each stash header"),
+    flag("overview", "list every stash before starting"),
+    flag("select", "mark stashes first, then act on them together"),
+    flag("tui", "review stashes in a full-screen interface"),
+    flag("no-binary-summary", "show binary files in the patch as is"),
+    flag("porcelain", "print only a parseable line for each action taken to stdout"),
+    short('q', flag("quiet", "show no diffs or headers")),
+    short('f', flag("force", "drop without checking the stash is applied")),
+    short('n', flag("dry-run", "print commands that would change the repository")),
+    flag("worktree", "commit branches in a separate worktree"),
+    flag("autostash", "set local changes aside while committing branches"),
+    flag("verify", "run commit hooks when committing branches"),
+    flag("no-verify", "skip commit hooks when committing branches"),
+    flag("difftool", "view stashes in git difftool"),
+    flag("current-date", "date branch commits now instead of when stashed"),
+    flag("stash-author", "credit branch commits to whoever made the stash"),
+    flag("number-commits", "number the commits a batch makes, oldest first"),
+    flag("edit-branch-name", "edit each rescue branch name before it is given"),
+    flag("index", "restore the staged changes when applying or popping"),
+    flag("confirm-quit", "ask before quitting with stashes left"),
+    short('v', flag("verbose", "log every git command")),
+    short('S', flag("sign", "sign branch commits")),
+    flag("color", "color output, or =always, =auto or =never"),
+    takes("grep", Value::Any, "only stashes whose message matches a regex"),
+    takes("script", Value::File, "read answers from a file"),
+    takes("log", Value::File, "append a line to a file for each action taken"),
+    takes("older-than", Value::Any, "only stashes older than an age like 30d"),
+    takes("since", Value::Any, "only stashes made on or after a date like 2024-03-01"),
+    takes("until", Value::Any, "only stashes made on or before a date like 2024-03-01"),
+    takes("mark-older-than", Value::Any, "mark stashes older than an age like 30d"),
+    takes("default-action", Value::Choices(&["skip", "help", "drop"]), "what empty input does"),
+    takes("branch-prefix", Value::Any, "prefix for branch names"),
+    takes("max-branch-length", Value::Any, "longest branch name to make"),
+    takes("start", Value::Any, "begin at this stash index"),
+    flag("word-diff", "show a word diff, or =color, =plain, =porcelain or =none"),
+    short('U', takes("context", Value::Any, "lines of context in diffs")),
+    flag("drop-applied", "drop every stash that is already applied"),
+    flag("branch-all", "commit every stash to its own branch"),
+    flag("apply-all", "apply every stash in turn"),
+    takes("collect-branch", Value::Any, "commit every stash onto one branch"),
+    takes("format", Value::Choices(&["text", "json"]), "output format for list"),
+    takes("range", Value::Any, "only stashes in a range like 3..6"),
+    takes("timeout", Value::Any, "seconds to wait before taking the default"),
+];
+
+/// The subcommands, other than the hidden `completions` itself.
+const SUBCOMMANDS: &[(&str, &str)] = &[
+    ("list", "print the stashes and exit"),
+    ("import", "turn a patch file into a new stash"),
+];
+
+const COMMAND: &str = "git-stash-inbox";
+
+pub(crate) fn script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    }
+}
+
+/// Named `_git_stash_inbox` so that git's own completion also uses it for
+/// `git stash-inbox`.
+fn bash() -> String {
+    let mut cases = String::new();
+    let mut plain = Vec::new();
+    for flag in FLAGS {
+        let names = match flag.short {
+            Some(short) => format!("--{}|-{short}", flag.long),
+            None => format!("--{}", flag.long),
+        };
+        let reply = match flag.value {
+            Value::None => continue,
+            Value::Any => "return".to_string(),
+            Value::File => "COMPREPLY=($(compgen -f -- \"$cur\")); return".to_string(),
+            Value::Choices(choices) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return", choices.join(" ")
+            ),
+        };
+        let _ = writeln!(cases, "        {names}) {reply} ;;");
+    }
+    for flag in FLAGS {
+        plain.push(format!("--{}", flag.long));
+        if let Some(short) = flag.short {
+            plain.push(format!("-{short}"));
+        }
+    }
+    plain.extend(SUBCOMMANDS.iter().map(|(name, _)| name.to_string()));
+    format!(
+        "# bash completion for {COMMAND}
+_git_stash_inbox() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    local prev=${{COMP_WORDS[COMP_CWORD-1]}}
+    case $prev in
+        import) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;
+{cases}    esac
+    COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))
+}}
+complete -F _git_stash_inbox {COMMAND}
+",
+        plain.join(" "),
+    )
+}
+
+fn zsh() -> String {
+    let mut specs = String::new();
+    for flag in FLAGS {
+        // `=` takes the value in the same word or the next, as does `+` for
+        // a short flag.
+        let (equals, plus, action) = match flag.value {
+            Value::None => ("", "", String::new()),
+            Value::Any => ("=", "+", format!(":{}: ", flag.long)),
+            Value::File => ("=", "+", format!(":{}:_files", flag.long)),
+            Value::Choices(choices) => {
+                ("=", "+", format!(":{}:({})", flag.long, choices.join(" ")))
+            }
+        };
+        let help = format!("[{}]{action}", flag.description);
+        let spec = match flag.short {
+            Some(short) => format!(
+                "'(-{short} --{0})'{{-{short}{plus},--{0}{equals}}}'{help}'", flag.long
+            ),
+            None => format!("'--{}{equals}{help}'", flag.long),
+        };
+        let _ = writeln!(specs, "    {spec} \\");
+    }
+    let subcommands: Vec<String> = SUBCOMMANDS.iter()
+        .map(|(name, description)| format!("{name}\\:\"{description}\""))
+        .collect();
+    format!(
+        "#compdef {COMMAND}
+
+_git-stash-inbox() {{
+  _arguments -S \\
+{specs}    '1:command:(({}))' \\
+    '2:patch file:_files'
+}}
+
+if [[ $zsh_eval_context[-1] == loadautofunc ]]; then
+  _git-stash-inbox \"$@\"
+else
+  compdef _git-stash-inbox {COMMAND}
+fi
+",
+        subcommands.join(" "),
+    )
+}
+
+fn fish() -> String {
+    let mut script = format!("# fish completion for {COMMAND}\ncomplete -c {COMMAND} -f\n");
+    for (name, description) in SUBCOMMANDS {
+        let _ = writeln!(
+            script,
+            "complete -c {COMMAND} -n __fish_use_subcommand -a {name} -d '{description}'"
+        );
+    }
+    let _ = writeln!(script, "complete -c {COMMAND} -n '__fish_seen_subcommand_from import' -F");
+    for flag in FLAGS {
+        let mut line = format!("complete -c {COMMAND} -l {}", flag.long);
+        if let Some(short) = flag.short {
+            let _ = write!(line, " -s {short}");
+        }
+        match flag.value {
+            Value::None => (),
+            Value::Any => line.push_str(" -x"),
+            Value::File => line.push_str(" -r -F"),
+            Value::Choices(choices) => {
+                let _ = write!(line, " -x -a '{}'", choices.join(" "));
+            }
+        }
+        let _ = writeln!(script, "{line} -d '{}'", flag.description);
+    }
+    script
+}
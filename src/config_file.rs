@@ -0,0 +1,274 @@
+//! The user's own config file, `~/.config/git-stash-inbox/config.toml`, for
+//! preferences that hold across every repository.
+//!
+//! Settings are taken from flags first, then git config, then this file, and
+//! only then the built-in defaults. Just the part of TOML that the settings
+//! need is understood: tables, comments, and single-line string, boolean and
+//! integer values.
+//!
+//! ```toml
+//! default-action = "skip"
+//! color = "auto"
+//! branch-prefix = "rescued/"
+//! max-branch-length = 40
+//! verify = true
+//!
+//! [keys]
+//! drop = "x"
+//! ```
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{error, Action, ColorMode, DefaultAction};
+
+/// Settings read from the config file; each is `None` when the file leaves
+/// it out.
+#[derive(Default)]
+pub(crate) struct UserConfig {
+    pub(crate) default_action: Option<DefaultAction>,
+    pub(crate) color: Option<ColorMode>,
+    pub(crate) branch_prefix: Option<String>,
+    pub(crate) max_branch_length: Option<usize>,
+    pub(crate) verify: Option<bool>,
+    /// Keys bound in the `[keys]` table.
+    pub(crate) keys: Vec<(Action, char)>,
+}
+
+impl UserConfig {
+    /// Read the config file, or the defaults if there isn't one.
+    pub(crate) fn load() -> io::Result<Self> {
+        let Some(path) = path() else {
+            return Ok(UserConfig::default());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(UserConfig::default()),
+            Err(e) => return Err(error(&format!("can't read {}: {e}", path.display()))),
+            Ok(text) => text,
+        };
+        Self::parse(&text).map_err(|(line, message)| {
+            error(&format!("{}:{line}: {message}", path.display()))
+        })
+    }
+
+    fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut config = UserConfig::default();
+        for entry in parse_toml(text)? {
+            let Entry { table, key, value, line } = entry;
+            let string = || match &value {
+                Value::String(s) => Ok(s.as_str()),
+                _ => Err((line, format!("{key} must be a string"))),
+            };
+            match (table.as_str(), key.as_str()) {
+                ("", "default-action") => {
+                    let parsed = DefaultAction::parse(&string()?.to_lowercase(), "default-action");
+                    config.default_action = Some(parsed.map_err(|e| (line, e.to_string()))?);
+                }
+                ("", "color") => {
+                    let value = string()?;
+                    let parsed = ColorMode::parse(value).map_err(|_| (line, format!(
+                        "invalid color '{value}'; expected always, auto, or never"
+                    )))?;
+                    config.color = Some(parsed);
+                }
+                ("", "branch-prefix") => config.branch_prefix = Some(string()?.to_string()),
+                ("", "max-branch-length") => match value {
+                    Value::Integer(max) if max > 0 => {
+                        config.max_branch_length = Some(max as usize);
+                    }
+                    _ => return Err((line, format!("{key} must be a positive integer"))),
+                },
+                ("", "verify") => match value {
+                    Value::Bool(verify) => config.verify = Some(verify),
+                    _ => return Err((line, format!("{key} must be true or false"))),
+                },
+                ("", _) => return Err((line, format!(
+                    "unknown setting '{key}'; expected default-action, color, branch-prefix, \
+                    max-branch-length, verify, or a [keys] table"
+                ))),
+                ("keys", _) => {
+                    let action = Action::ALL.into_iter()
+                        .find(|action| action.name() == key)
+                        .ok_or_else(|| (line, format!("unknown action '{key}' in [keys]")))?;
+                    let value = string()?;
+                    let mut chars = value.chars();
+                    let key = match (chars.next(), chars.next()) {
+                        (Some(key), None) if key.is_ascii_graphic() => key,
+                        _ => return Err((line, format!(
+                            "invalid key '{value}' for {}; expected a single character",
+                            action.name(),
+                        ))),
+                    };
+                    config.keys.push((action, key));
+                }
+                _ => return Err((line, format!("unknown table [{table}]; expected [keys]"))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Where the config file lives, following the XDG base directory spec.
+fn path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(dir.join("git-stash-inbox").join("config.toml"))
+}
+
+enum Value {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+}
+
+/// A `key = value` line, under the table it appeared in, or "" at the top.
+struct Entry {
+    table: String,
+    key: String,
+    value: Value,
+    /// The 1-based line number, for errors.
+    line: usize,
+}
+
+/// Split `text` into its entries, or the line number and reason it couldn't.
+fn parse_toml(text: &str) -> Result<Vec<Entry>, (usize, String)> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut table = String::new();
+    let mut tables = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let at = |message: &str| (line_number, message.to_string());
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            if header.starts_with('[') {
+                return Err(at("arrays of tables aren't supported"));
+            }
+            let (name, rest) = header.split_once(']')
+                .ok_or_else(|| at("missing ] after table name"))?;
+            let (name, after) = parse_key(name.trim()).map_err(|e| at(&e))?;
+            if !after.is_empty() {
+                return Err(at("dotted table names aren't supported"));
+            }
+            expect_end(rest).map_err(|e| at(&e))?;
+            if tables.contains(&name) {
+                return Err(at(&format!("table [{name}] is defined twice")));
+            }
+            tables.push(name.clone());
+            table = name;
+            continue;
+        }
+        let (key, rest) = parse_key(line).map_err(|e| at(&e))?;
+        let rest = rest.trim_start().strip_prefix('=').ok_or_else(|| {
+            at(&format!("expected = after {key}"))
+        })?;
+        let (value, rest) = parse_value(rest.trim_start()).map_err(|e| at(&e))?;
+        expect_end(rest).map_err(|e| at(&e))?;
+        if entries.iter().any(|e| e.table == table && e.key == key) {
+            return Err(at(&format!("{key} is set twice")));
+        }
+        entries.push(Entry { table: table.clone(), key, value, line: line_number });
+    }
+    Ok(entries)
+}
+
+/// A bare or quoted key at the start of `text`, and what follows it.
+fn parse_key(text: &str) -> Result<(String, &str), String> {
+    if text.starts_with(['"', '\'']) {
+        return match parse_value(text)? {
+            (Value::String(key), rest) => Ok((key, rest)),
+            _ => unreachable!("quotes always start a string"),
+        };
+    }
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(text.len());
+    if end == 0 {
+        return Err(format!("expected a key, found '{text}'"));
+    }
+    if text[end..].trim_start().starts_with('.') {
+        return Err("dotted keys aren't supported".to_string());
+    }
+    Ok((text[..end].to_string(), &text[end..]))
+}
+
+/// The value at the start of `text`, and what follows it.
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    if text.starts_with("\"\"\"") || text.starts_with("'''") {
+        return Err("multi-line strings aren't supported".to_string());
+    }
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        return parse_basic_string(rest);
+    }
+    if text.starts_with(['[', '{']) {
+        return Err("arrays and inline tables aren't supported".to_string());
+    }
+    let end = text.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "" => return Err("expected a value".to_string()),
+        _ => match word.replace('_', "").parse() {
+            Ok(n) => Value::Integer(n),
+            Err(_) => return Err(format!(
+                "invalid value '{word}'; strings need quotes, like \"{word}\""
+            )),
+        },
+    };
+    Ok((value, rest))
+}
+
+/// A double-quoted string with its escapes, starting after the opening quote.
+fn parse_basic_string(text: &str) -> Result<(Value, &str), String> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((Value::String(value), &text[i + 1..])),
+            '\\' => {
+                let escaped = match chars.next().map(|(_, c)| c) {
+                    Some('b') => '\u{8}',
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some('f') => '\u{c}',
+                    Some('r') => '\r',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some(u @ ('u' | 'U')) => {
+                        let len = if u == 'u' { 4 } else { 8 };
+                        let hex: String = chars.by_ref().take(len).map(|(_, c)| c).collect();
+                        u32::from_str_radix(&hex, 16).ok()
+                            .filter(|_| hex.len() == len)
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\{u}{hex}"))?
+                    }
+                    Some(other) => return Err(format!("invalid escape \\{other}")),
+                    None => break,
+                };
+                value.push(escaped);
+            }
+            _ => value.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+/// Allow only whitespace and a comment after a value or table name.
+fn expect_end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected '{rest}' at the end of the line"))
+    }
+}
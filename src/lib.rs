@@ -0,0 +1,4382 @@
+//! Triage git stashes: list them, see whether they're already applied, and
+//! drop them or rescue them onto branches.
+//!
+//! The `git-stash-inbox` binary is a thin wrapper around [`cli_main`]; the
+//! rest of the public API is for tools that want the same stash handling
+//! without the interactive loop. Everything runs `git` in the current
+//! directory.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+#[cfg(windows)]
+use std::os::windows::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod completions;
+mod config_file;
+mod messages;
+#[cfg(unix)]
+mod tui;
+
+use config_file::UserConfig;
+use messages::Message;
+
+macro_rules! tty_af {
+    ($num:literal) => { concat!("\x1b[", $num, "m") };
+}
+
+const TTY_CLEAR: &str = tty_af!(0);
+const TTY_BOLD: &str = tty_af!(1);
+const TTY_RED: &str = tty_af!(31);
+const TTY_GREEN: &str = tty_af!(32);
+const TTY_YELLOW: &str = tty_af!(33);
+const TTY_BLUE: &str = tty_af!(34);
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+#[cfg(unix)]
+const SIGPIPE: i32 = 13;
+
+/// Written on interrupt to end any partial colored output and show the cursor.
+const TTY_RESET: &str = concat!(tty_af!(0), "\x1b[?25h\n");
+
+/// Exit status when an error stopped the session or any action failed.
+const EXIT_FAILURE: i32 = 1;
+
+/// Exit status for a session interrupted by Ctrl-C, as shells report SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::{c_int, c_short, c_void};
+
+    #[repr(C)]
+    pub struct PollFd {
+        pub fd: c_int,
+        pub events: c_short,
+        pub revents: c_short,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub type Nfds = std::os::raw::c_ulong;
+    #[cfg(not(target_os = "linux"))]
+    pub type Nfds = std::os::raw::c_uint;
+
+    extern "C" {
+        /// `handler` is a function pointer, or `SIG_DFL` or `SIG_IGN`.
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+        pub fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+        pub fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+        pub fn poll(fds: *mut PollFd, nfds: Nfds, timeout: c_int) -> c_int;
+        pub fn _exit(status: c_int) -> !;
+        pub fn kill(pid: c_int, sig: c_int) -> c_int;
+        pub fn dup(fd: c_int) -> c_int;
+        pub fn dup2(fd: c_int, new_fd: c_int) -> c_int;
+        pub fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    }
+
+    pub const SIG_DFL: usize = 0;
+    pub const SIG_IGN: usize = 1;
+    pub const EPERM: i32 = 1;
+    pub const POLLIN: c_short = 1;
+    pub const F_SETFD: c_int = 2;
+    pub const FD_CLOEXEC: c_int = 1;
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::os::raw::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    pub const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    pub const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetStdHandle(std_handle: u32) -> Handle;
+        pub fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+        pub fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+        pub fn SetStdHandle(std_handle: u32, handle: Handle) -> i32;
+    }
+}
+
+/// Make a console stream interpret ANSI escapes. Always true off Windows;
+/// on Windows, false for consoles too old to support virtual terminal mode.
+fn enable_ansi(stream: Stream) -> bool {
+    #[cfg(windows)]
+    unsafe {
+        let id = match stream {
+            Stream::Stdout => sys::STD_OUTPUT_HANDLE,
+            Stream::Stderr => sys::STD_ERROR_HANDLE,
+        };
+        let handle = sys::GetStdHandle(id);
+        let mut mode = 0;
+        if sys::GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        sys::SetConsoleMode(handle, mode | sys::ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = stream;
+        true
+    }
+}
+
+/// Set once an interrupt is being handled, so repeated signals just exit.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Set when an action fails but the session carries on, so that it still
+/// exits with `EXIT_FAILURE`.
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+fn record_failure() {
+    FAILED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: i32) {
+    // Only async-signal-safe calls are allowed here, hence raw `write`.
+    // The terminal itself is only ever raw inside `read_key`, which turns
+    // Ctrl-C into a keypress rather than a signal.
+    let reset = if COLOR.get().is_some_and(|c| c.stdout) { TTY_RESET } else { "\n" };
+    unsafe {
+        if !INTERRUPTED.swap(true, Ordering::SeqCst) {
+            sys::write(1, reset.as_ptr().cast(), reset.len());
+        }
+        sys::_exit(EXIT_INTERRUPTED);
+    }
+}
+
+fn install_interrupt_handler() {
+    #[cfg(unix)]
+    unsafe {
+        sys::signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize);
+    }
+}
+
+/// How many `IgnoreInterrupts` are held.
+static IGNORING_INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// While held, Ctrl-C is ignored, as git does while a child has the
+/// terminal, like the pager or an editor. The signal goes to the whole
+/// foreground process group, so the child still gets it to act on, while
+/// exiting here would leave whatever the child was part of half done. The
+/// child has to be set up with `default_interrupts`.
+struct IgnoreInterrupts;
+
+impl IgnoreInterrupts {
+    fn new() -> Self {
+        if IGNORING_INTERRUPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+            #[cfg(unix)]
+            unsafe {
+                sys::signal(SIGINT, sys::SIG_IGN);
+            }
+        }
+        IgnoreInterrupts
+    }
+}
+
+impl Drop for IgnoreInterrupts {
+    fn drop(&mut self) {
+        if IGNORING_INTERRUPTS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            install_interrupt_handler();
+        }
+    }
+}
+
+/// Have `cmd` take Ctrl-C as usual, rather than inherit it ignored from
+/// `IgnoreInterrupts`, which a shell couldn't then undo.
+fn default_interrupts(cmd: &mut Command) {
+    #[cfg(unix)]
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(cmd, || {
+            sys::signal(SIGINT, sys::SIG_DFL);
+            Ok(())
+        });
+    }
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+/// Handle a Ctrl-C read as a keypress; the terminal must already be restored.
+fn interrupt() -> ! {
+    if !INTERRUPTED.swap(true, Ordering::SeqCst) {
+        let reset = if COLOR.get().is_some_and(|c| c.stdout) { TTY_RESET } else { "\n" };
+        print!("{reset}");
+        let _ = io::stdout().flush();
+    }
+    std::process::exit(EXIT_INTERRUPTED);
+}
+
+/// When to emit ANSI color codes, as chosen by `--color` or git config.
+#[derive(Clone, Copy, PartialEq)]
+enum ColorMode {
+    Always,
+    Auto,
+    Never,
+}
+
+impl ColorMode {
+    fn parse(value: &str) -> io::Result<Self> {
+        match value {
+            "always" => Ok(ColorMode::Always),
+            "auto" => Ok(ColorMode::Auto),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(error(&format!(
+                "invalid --color value '{value}'; expected always, auto, or never"
+            ))),
+        }
+    }
+
+    /// Read a git `color.*` setting, where `true` means `auto`.
+    fn from_config(key: &str) -> io::Result<Option<Self>> {
+        let Some(value) = git_config(key)? else {
+            return Ok(None);
+        };
+        match value.to_lowercase().as_str() {
+            "always" => Ok(Some(ColorMode::Always)),
+            "auto" | "true" | "yes" | "on" | "1" => Ok(Some(ColorMode::Auto)),
+            "never" | "false" | "no" | "off" | "0" => Ok(Some(ColorMode::Never)),
+            _ => Err(error(&format!("invalid {key} value '{value}'"))),
+        }
+    }
+
+    fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                is_terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Where color is enabled, decided once at startup.
+#[derive(Clone, Copy)]
+struct Colors {
+    stdout: bool,
+    stderr: bool,
+    /// Whether git should colorize the diffs it prints to stdout.
+    diff: bool,
+}
+
+static COLOR: OnceLock<Colors> = OnceLock::new();
+
+/// Decide where to use color. `--color` wins over git's `color.ui`, and for
+/// diffs `color.diff` is consulted before `color.ui`.
+fn init_color(flag: Option<ColorMode>, fallback: Option<ColorMode>) -> io::Result<()> {
+    let ui = match flag {
+        Some(mode) => mode,
+        None => ColorMode::from_config("color.ui")?.or(fallback).unwrap_or(ColorMode::Auto),
+    };
+    let diff = match flag {
+        Some(mode) => mode,
+        None => ColorMode::from_config("color.diff")?.unwrap_or(ui),
+    };
+    let stdout = io::stdout().is_terminal();
+    let stderr = io::stderr().is_terminal();
+    // Escapes written to a console that can't interpret them are just noise,
+    // but redirected output gets them whenever color is forced.
+    let stdout_ansi = !stdout || enable_ansi(Stream::Stdout);
+    let stderr_ansi = !stderr || enable_ansi(Stream::Stderr);
+    let _ = COLOR.set(Colors {
+        stdout: stdout_ansi && ui.enabled(stdout),
+        stderr: stderr_ansi && ui.enabled(stderr),
+        diff: stdout_ansi && diff.enabled(stdout),
+    });
+    Ok(())
+}
+
+fn diff_color() -> bool {
+    COLOR.get().is_some_and(|c| c.diff)
+}
+
+/// The `--color` option that makes git's diff output follow our own color
+/// decision, since git can't see where its piped output ends up.
+fn diff_color_arg() -> &'static str {
+    if diff_color() { "--color=always" } else { "--color=never" }
+}
+
+/// The ANSI codes to use on one output stream; all empty when color is off.
+#[derive(Clone, Copy)]
+struct Palette {
+    clear: &'static str,
+    bold: &'static str,
+    red: &'static str,
+    green: &'static str,
+    yellow: &'static str,
+    blue: &'static str,
+}
+
+fn palette(stream: Stream) -> Palette {
+    let enabled = COLOR.get().is_some_and(|c| match stream {
+        Stream::Stdout => c.stdout,
+        Stream::Stderr => c.stderr,
+    });
+    if enabled {
+        Palette {
+            clear: TTY_CLEAR,
+            bold: TTY_BOLD,
+            red: TTY_RED,
+            green: TTY_GREEN,
+            yellow: TTY_YELLOW,
+            blue: TTY_BLUE,
+        }
+    } else {
+        Palette { clear: "", bold: "", red: "", green: "", yellow: "", blue: "" }
+    }
+}
+
+fn stash_ref(id: u32) -> String {
+    format!("stash@{{{}}}", id)
+}
+
+/// The first line of `--porcelain` output, naming the format's version.
+const PORCELAIN_HEADER: &str = "# porcelain v1";
+
+/// With `--porcelain`, print a line for an action taken on a stash: `event`
+/// in capitals, the stash's ref and hash, and the branch or tag it was saved
+/// to, if any. The words are separated by single spaces and none contain one.
+/// The same line, after a timestamp and followed by a tab and the stash's
+/// message, goes to the `--log` file.
+fn print_event(args: &Args, event: &str, stash: &Stash, saved_to: Option<&str>) {
+    let mut line = format!("{event} {} {}", stash_ref(stash.index), stash.hash);
+    if let Some(saved_to) = saved_to {
+        line.push(' ');
+        line.push_str(saved_to);
+    }
+    log_line(&format!("{line}\t{}", stash.message));
+    if let Some(events) = PORCELAIN.get().filter(|_| args.porcelain) {
+        let mut events = events.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(events, "{line}") {
+            report_error(&error(&format!("can't write porcelain output: {e}")));
+        }
+    }
+}
+
+/// Where `--porcelain` events go: the original stdout, once `open_porcelain`
+/// has taken it.
+static PORCELAIN: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Keep stdout for `--porcelain` events alone, and write the header to it.
+/// Everything else written to stdout from here on, by this process or the git
+/// commands it runs, goes to stderr instead.
+fn open_porcelain() -> io::Result<()> {
+    io::stdout().flush()?;
+    let mut events = take_stdout()?;
+    writeln!(events, "{PORCELAIN_HEADER}")?;
+    let _ = PORCELAIN.set(Mutex::new(events));
+    Ok(())
+}
+
+/// Point stdout at stderr, returning the stream it was.
+#[cfg(unix)]
+fn take_stdout() -> io::Result<File> {
+    use std::os::unix::io::FromRawFd;
+    unsafe {
+        let fd = sys::dup(1);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let events = File::from_raw_fd(fd);
+        // Kept from the commands run, so that one left running, like a
+        // clipboard tool, doesn't hold the events open.
+        if sys::fcntl(fd, sys::F_SETFD, sys::FD_CLOEXEC) < 0 || sys::dup2(2, 1) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(events)
+    }
+}
+
+/// Point stdout at stderr, returning the stream it was.
+#[cfg(windows)]
+fn take_stdout() -> io::Result<File> {
+    use std::os::windows::io::FromRawHandle;
+    unsafe {
+        let handle = sys::GetStdHandle(sys::STD_OUTPUT_HANDLE);
+        let stderr = sys::GetStdHandle(sys::STD_ERROR_HANDLE);
+        if sys::SetStdHandle(sys::STD_OUTPUT_HANDLE, stderr) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(File::from_raw_handle(handle))
+    }
+}
+
+/// The `--log` file, once opened; see `open_log`.
+static LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Open `--log` for appending, so that a file that can't be written is an
+/// error before the session starts rather than partway through, and note the
+/// session's start in it.
+fn open_log(path: &Path) -> io::Result<()> {
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(path).map_err(|e| {
+        error(&format!("can't open log file {}: {e}", path.display()))
+    })?;
+    let _ = LOG.set(Mutex::new(file));
+    let dir = std::env::current_dir()?;
+    let dry_run = if DRY_RUN.load(Ordering::Relaxed) { " (dry run)" } else { "" };
+    log_line(&format!("START {}{dry_run}", dir.display()));
+    Ok(())
+}
+
+/// Append `line` to the `--log` file, if there is one, after the time. The
+/// session goes on if the write fails, with the failure reported.
+fn log_line(line: &str) {
+    let Some(log) = LOG.get() else {
+        return;
+    };
+    let mut file = log.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = writeln!(file, "{} {line}", utc_now()) {
+        report_error(&error(&format!("can't write to the log file: {e}")));
+    }
+}
+
+/// The time now in UTC, like `2024-03-01T14:30:00Z`.
+fn utc_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let time = secs % 86_400;
+    // Howard Hinnant's civil_from_days, for days since 1970-01-01.
+    let days = secs / 86_400 + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600, time / 60 % 60, time % 60,
+    )
+}
+
+/// `stash_ref` for an index the user gave, checked against the `count`
+/// stashes there are, so a bad one gets a clear `InvalidInput` error up front
+/// rather than whatever git makes of it later. `flag` names where it came from.
+fn checked_stash_ref(index: u32, count: usize, flag: &str) -> io::Result<String> {
+    if index as usize >= count {
+        let noun = if count == 1 { "stash exists" } else { "stashes exist" };
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "{flag}: stash index {index} out of range; only {count} {noun}"
+        )));
+    }
+    Ok(stash_ref(index))
+}
+
+/// A single entry from `git stash list`.
+#[derive(Clone)]
+pub struct Stash {
+    /// Position in the stash list, as in `stash@{index}`.
+    pub index: u32,
+    /// The full message, e.g. "On main: fix the thing".
+    pub message: String,
+    /// The branch the stash was made on, if the message says.
+    pub branch: String,
+    /// Hash of the stash commit.
+    pub hash: String,
+    /// Creation time in strict ISO 8601 format.
+    pub date: String,
+    /// Creation time relative to now, e.g. "3 days ago".
+    pub age: String,
+    /// Creation time in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Name of whoever made the stash.
+    pub author: String,
+    /// Email address of whoever made the stash.
+    pub email: String,
+}
+
+/// Extra details shown in a stash's header.
+struct HeaderNotes {
+    /// The stash's changes are already in the working tree.
+    applied: bool,
+    /// The stash is older than `--mark-older-than`.
+    old: bool,
+    /// How big the stash is, from `git_stash_shortstat`.
+    shortstat: Option<String>,
+}
+
+/// The `git stash list` format understood by `Stash::parse`.
+const STASH_FORMAT: &str = "--format=%gd%x00%H%x00%cI%x00%cr%x00%ct%x00%an%x00%ae%x00%gs";
+
+impl Stash {
+    /// Parse one `STASH_FORMAT` line of `git stash list` output.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(8, '\0');
+        let index = fields.next()?
+            .strip_prefix("stash@{")?
+            .strip_suffix('}')?
+            .parse()
+            .ok()?;
+        let hash = fields.next()?.to_string();
+        let date = fields.next()?.to_string();
+        let age = fields.next()?.to_string();
+        let timestamp = fields.next()?.parse().ok()?;
+        let author = fields.next()?.to_string();
+        let email = fields.next()?.to_string();
+        let message = fields.next()?.to_string();
+        // Stash messages look like "WIP on <branch>: ..." or "On <branch>: ...".
+        let branch = message.strip_prefix("WIP on ")
+            .or_else(|| message.strip_prefix("On "))
+            .and_then(|rest| rest.split_once(": "))
+            .map(|(branch, _)| branch.to_string())
+            .unwrap_or_default();
+        Some(Stash { index, message, branch, hash, date, age, timestamp, author, email })
+    }
+
+    /// Print a one-line summary of where and when the stash was made, with
+    /// any `notes` about it.
+    fn print_header(&self, notes: &HeaderNotes) {
+        let Palette { clear, bold, red, green, yellow, .. } = palette(Stream::Stdout);
+        let mut tags = String::new();
+        if notes.applied {
+            tags.push_str(&format!(" {green}{}{clear}", Message::AppliedTag));
+        }
+        if notes.old {
+            tags.push_str(&format!(" {bold}{red}{}{clear}", Message::OldTag));
+        }
+        println!(
+            "{bold}{yellow}{}{clear} ({}, {} by {}) {}{tags}",
+            stash_ref(self.index), self.branch, self.age, self.author, self.subject(),
+        );
+        if let Some(shortstat) = notes.shortstat.as_deref().filter(|s| !s.is_empty()) {
+            println!("    {shortstat}");
+        }
+    }
+
+    /// Whether the stash was made more than `secs` seconds ago.
+    fn older_than(&self, secs: u64) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        now.saturating_sub(self.timestamp) > secs
+    }
+
+    /// As much of the stash's ISO date as a `parse_date` bound of `len`
+    /// characters covers.
+    fn date_prefix(&self, len: usize) -> &str {
+        self.date.get(..len).unwrap_or(&self.date)
+    }
+
+    /// The stash message without its `On <branch>: ` prefix.
+    pub fn subject(&self) -> &str {
+        self.message.split_once(": ")
+            .map_or(&self.message, |(_, subject)| subject)
+    }
+
+    /// The stash message with its subject replaced, keeping any prefix.
+    fn with_subject(&self, subject: &str) -> String {
+        let prefix_len = self.message.len() - self.subject().len();
+        format!("{}{subject}", &self.message[..prefix_len])
+    }
+}
+
+/// The stashes selected by `--grep`, `--older-than`, `--since`, `--until` and
+/// `--range`.
+fn selected_stashes(args: &Args) -> io::Result<Vec<Stash>> {
+    let mut stashes = list_stashes(args.grep.as_deref())?;
+    if let Some(range) = &args.range {
+        let count = match args.grep {
+            Some(_) => list_stashes(None)?.len(),
+            None => stashes.len(),
+        };
+        // The range is half-open, so its last stash is one before the end.
+        let flag = format!("--range {}..{}", range.start, range.end);
+        checked_stash_ref(range.end - 1, count, &flag)?;
+        stashes.retain(|stash| range.contains(&stash.index));
+    }
+    if let Some(secs) = args.older_than {
+        stashes.retain(|stash| stash.older_than(secs));
+    }
+    if let Some(since) = &args.since {
+        stashes.retain(|stash| stash.date_prefix(since.len()) >= since.as_str());
+    }
+    if let Some(until) = &args.until {
+        stashes.retain(|stash| stash.date_prefix(until.len()) <= until.as_str());
+    }
+    Ok(stashes)
+}
+
+/// After an action that may have dropped the stash at `pos`, take it out of
+/// `stashes` and renumber the older ones, rather than listing every stash
+/// again, which made each action slower the more stashes there were. One
+/// `rev-parse` says whether git dropped it; if the stash list changed in some
+/// other way too, it's listed again after all.
+fn forget_dropped(stashes: &mut Vec<Stash>, pos: usize, args: &Args) -> io::Result<()> {
+    let stash = &stashes[pos];
+    let output = git(["rev-parse", "-q", "--verify", &stash_ref(stash.index)]).output()?;
+    let now_at_index = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if now_at_index == stash.hash {
+        return Ok(());
+    }
+    let next = stashes.get(pos + 1).filter(|next| next.index == stash.index + 1);
+    if next.is_some_and(|next| next.hash != now_at_index) {
+        *stashes = selected_stashes(args)?;
+        return Ok(());
+    }
+    let index = stash.index;
+    remove_stashes(stashes, &[index]);
+    Ok(())
+}
+
+/// Take the stashes git has dropped, by their index before any of the drops,
+/// out of `stashes`, moving each older stash up past those dropped above it.
+fn remove_stashes(stashes: &mut Vec<Stash>, dropped: &[u32]) {
+    stashes.retain(|stash| !dropped.contains(&stash.index));
+    for stash in stashes.iter_mut() {
+        let newer = dropped.iter().filter(|&&index| index < stash.index).count();
+        stash.index -= newer as u32;
+    }
+}
+
+/// List stashes, newest first, optionally keeping only those whose message
+/// matches the extended regex `grep`.
+pub fn list_stashes(grep: Option<&str>) -> io::Result<Vec<Stash>> {
+    let mut list = if read_only() {
+        // `git stash list` wants a work tree, but is just this underneath.
+        let has_ref = git(["rev-parse", "-q", "--verify", "refs/stash"])
+            .stdout(Stdio::null())
+            .status()?
+            .success();
+        if !has_ref {
+            return Ok(Vec::new());
+        }
+        git(["log", "-g", "--first-parent", "-m", STASH_FORMAT, "refs/stash"])
+    } else {
+        git(["stash", "list", STASH_FORMAT])
+    };
+    if let Some(pattern) = grep {
+        list.args(["-E", &format!("--grep-reflog={pattern}")]);
+    }
+    let output = list.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(error("failed to list stashes"));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            Stash::parse(line)
+                .ok_or_else(|| error(&format!("unexpected stash list line: {line}")))
+        })
+        .collect()
+}
+
+/// Whether commands from `git_mut` are printed instead of run.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// How many times `-v` was given: at 1, every git command and its exit
+/// status is logged to stderr; at 2, so is any stderr the command's output
+/// was captured with.
+static VERBOSE: AtomicU8 = AtomicU8::new(0);
+
+fn verbosity() -> u8 {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// Set in a bare repository, where there's no work tree to apply stashes to
+/// or commit them from, so stashes can only be looked at.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+fn read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// Set while someone is at the keyboard to release a lock, so that a git
+/// command failing on another process's `index.lock` asks to retry; see
+/// `Git::retry_locked`. Otherwise the command is an error.
+static RETRY_LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// A git invocation, wrapping `Command` so that commands which modify the
+/// repository can be skipped under `--dry-run`.
+struct Git {
+    cmd: Command,
+    mutating: bool,
+    /// Whether the command gives the terminal to the user, through the
+    /// pager, an editor or a difftool; see `IgnoreInterrupts`.
+    foreground: bool,
+    dir: Option<PathBuf>,
+}
+
+impl Git {
+    fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.cmd.arg(arg);
+        self
+    }
+
+    fn args<I, S>(&mut self, args: I) -> &mut Self
+    where I: IntoIterator<Item = S>,
+          S: AsRef<OsStr>
+    {
+        self.cmd.args(args);
+        self
+    }
+
+    fn foreground(&mut self) -> &mut Self {
+        default_interrupts(&mut self.cmd);
+        self.foreground = true;
+        self
+    }
+
+    fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.cmd.current_dir(&dir);
+        self.dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.cmd.env(key, value);
+        self
+    }
+
+    fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.cmd.stdin(cfg);
+        self
+    }
+
+    fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.cmd.stdout(cfg);
+        self
+    }
+
+    fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.cmd.stderr(cfg);
+        self
+    }
+
+    /// The command line, quoted for display.
+    fn display(&self) -> String {
+        let mut line = String::from("git");
+        if let Some(dir) = &self.dir {
+            line.push_str(&format!(" -C '{}'", dir.display()));
+        }
+        for arg in self.cmd.get_args() {
+            let arg = arg.to_string_lossy();
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                line.push_str(&format!(" '{arg}'"));
+            } else {
+                line.push_str(&format!(" {arg}"));
+            }
+        }
+        line
+    }
+
+    /// Whether this command should be reported rather than run.
+    fn skipped(&self) -> bool {
+        let skip = self.mutating && DRY_RUN.load(Ordering::Relaxed);
+        if skip {
+            println!("{}", Message::WouldRun { command: &self.display() });
+        }
+        skip
+    }
+
+    fn log_start(&self) {
+        if verbosity() > 0 {
+            eprintln!("+ {}", self.display());
+        }
+    }
+
+    fn log_status(&self, status: Result<&ExitStatus, &io::Error>) {
+        if verbosity() > 0 {
+            match status {
+                Ok(status) => eprintln!("+ {status}"),
+                Err(e) => eprintln!("+ failed to run: {e}"),
+            }
+        }
+    }
+
+    fn status(&mut self) -> io::Result<ExitStatus> {
+        if self.skipped() {
+            return Ok(ExitStatus::from_raw(0));
+        }
+        loop {
+            self.log_start();
+            let _ignoring = self.foreground.then(IgnoreInterrupts::new);
+            let status = self.cmd.status();
+            self.log_status(status.as_ref());
+            if !status.as_ref().is_ok_and(|s| !s.success()) || !self.retry_locked()? {
+                return status;
+            }
+        }
+    }
+
+    fn output(&mut self) -> io::Result<Output> {
+        if self.skipped() {
+            return Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            });
+        }
+        loop {
+            self.log_start();
+            let output = self.cmd.output();
+            self.log_status(output.as_ref().map(|o| &o.status));
+            if let Ok(output) = &output {
+                if verbosity() > 1 && !output.stderr.is_empty() {
+                    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+                }
+            }
+            if !output.as_ref().is_ok_and(|o| !o.status.success()) || !self.retry_locked()? {
+                return output;
+            }
+        }
+    }
+
+    /// After a mutating command failed, check whether another git process
+    /// holds the index lock, which git only reports as a failure like any
+    /// other. If so, ask whether to run the command again once it's
+    /// released, when `RETRY_LOCKED` allows, or else fail with an error
+    /// saying why. Read-only commands fail for ordinary reasons, like
+    /// `git config --get` on an unset key, and don't take the lock anyway.
+    fn retry_locked(&self) -> io::Result<bool> {
+        if !self.mutating {
+            return Ok(false);
+        }
+        // Not through `git()`, whose failure would come back here.
+        let mut rev_parse = Command::new("git");
+        if let Some(dir) = &self.dir {
+            rev_parse.current_dir(dir);
+        }
+        let output = rev_parse.args(["rev-parse", "--git-path", "index.lock"])
+            .stderr(Stdio::null())
+            .output()?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let lock = match &self.dir {
+            Some(dir) => dir.join(&path),
+            None => PathBuf::from(&path),
+        };
+        if !output.status.success() || !lock.exists() {
+            return Ok(false);
+        }
+        if !RETRY_LOCKED.load(Ordering::Relaxed) {
+            return Err(error(&format!(
+                "{} failed because another git process is using this repository; {} exists",
+                self.display(), lock.display(),
+            )));
+        }
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::IndexLocked { path: &lock.to_string_lossy() });
+        print!("{} ", Message::RetryLocked);
+        io::stdout().flush()?;
+        let answer = match read_line() {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!();
+                return Ok(false);
+            }
+            answer => answer?,
+        };
+        // Nobody answered in time, so there's nobody to release the lock.
+        if TIMED_OUT.swap(false, Ordering::Relaxed) {
+            return Ok(false);
+        }
+        Ok(answer.trim().is_empty() || is_yes(&answer))
+    }
+
+    /// Spawn the command. Only used for read-only commands, so this always
+    /// runs, even under `--dry-run`. Its exit status is the caller's to
+    /// collect, so only the command itself is logged.
+    fn spawn(&mut self) -> io::Result<Child> {
+        self.log_start();
+        self.cmd.spawn()
+    }
+}
+
+/// A read-only git command, run even under `--dry-run`.
+#[must_use = "git command builder was not run. Try calling `status`"]
+fn git<I, S>(args: I) -> Git
+where I: IntoIterator<Item = S>,
+      S: AsRef<OsStr>
+{
+    let mut cmd = Command::new("git");
+    cmd.args(args);
+    Git { cmd, mutating: false, foreground: false, dir: None }
+}
+
+/// A git command that modifies the repository or working tree.
+#[must_use = "git command builder was not run. Try calling `status`"]
+fn git_mut<I, S>(args: I) -> Git
+where I: IntoIterator<Item = S>,
+      S: AsRef<OsStr>
+{
+    Git { mutating: true, ..git(args) }
+}
+
+/// The value of a git config key, or `None` if it isn't set.
+fn git_config(key: &str) -> io::Result<Option<String>> {
+    let output = git(["config", "--get", key]).output()?;
+    // `git config --get` exits with 1 when the key is unset.
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string()))
+}
+
+/// The value of a boolean git config key, or `None` if it isn't set.
+fn git_config_bool(key: &str) -> io::Result<Option<bool>> {
+    let output = git(["config", "--type=bool", "--get", key]).output()?;
+    match output.status.code() {
+        Some(0) => Ok(Some(output.stdout.trim_ascii() == b"true")),
+        Some(1) => Ok(None),
+        _ => Err(error(&format!("invalid {key} value; expected a boolean"))),
+    }
+}
+
+/// Fail early with a clear message if git can't be run, or if we aren't
+/// inside a work tree, rather than letting the first real command fail in a
+/// confusing way.
+fn check_git_repo() -> io::Result<()> {
+    let output = match git(["rev-parse", "--is-inside-work-tree"]).output() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(error("git executable not found on PATH"));
+        }
+        result => result?,
+    };
+    if output.status.success() && output.stdout.trim_ascii() == b"true" {
+        return Ok(());
+    }
+    // Inside a `.git` directory this succeeds but prints "false" too, but
+    // that isn't a bare repository.
+    let bare = git(["rev-parse", "--is-bare-repository"]).output()?;
+    if bare.status.success() && bare.stdout.trim_ascii() == b"true" {
+        READ_ONLY.store(true, Ordering::Relaxed);
+        return Ok(());
+    }
+    Err(error("not inside a git work tree; run this from within a repository"))
+}
+
+fn has_local_changes() -> io::Result<bool> {
+    let has = !git(["status", "--porcelain"])
+        .output()?
+        .stdout
+        .is_empty();
+    Ok(has)
+}
+
+fn error(message: &str) -> io::Error {
+    io::Error::other(message)
+}
+
+/// The pager git would use for `git stash`, honoring `pager.stash`, then
+/// `GIT_PAGER`, `core.pager` and `PAGER`. `None` when paging is disabled or
+/// stdout is not a terminal.
+fn pager() -> io::Result<Option<String>> {
+    if !io::stdout().is_terminal() {
+        return Ok(None);
+    }
+    match git_config("pager.stash")?.as_deref() {
+        Some("false" | "no" | "off" | "0") => return Ok(None),
+        Some("true" | "yes" | "on" | "1") | None => (),
+        Some(pager) => return Ok(Some(pager.to_string())),
+    }
+    let output = git(["var", "GIT_PAGER"]).output()?;
+    let pager = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || pager.is_empty() || pager == "cat" {
+        return Ok(None);
+    }
+    Ok(Some(pager))
+}
+
+/// Whether a process exited cleanly or only because its reader went away.
+fn exited_ok(status: ExitStatus) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        if status.signal() == Some(SIGPIPE) {
+            return Ok(true);
+        }
+    }
+    let code = status.code()
+        .ok_or_else(|| error("terminated by signal"))?;
+    Ok(code == 0 || code == 141)
+}
+
+/// `git stash show`, or `git diff` when limiting to `paths`, which `git stash
+/// show` doesn't accept, or in a bare repository, where it refuses to run.
+/// Options can be added before `stash_diff_target`.
+fn stash_diff(paths: &[String]) -> Git {
+    if paths.is_empty() && !read_only() {
+        git(["--no-pager", "stash", "show"])
+    } else {
+        git(["--no-pager", "diff"])
+    }
+}
+
+/// Finish a `stash_diff` command with the stash to show.
+fn stash_diff_target(diff: &mut Git, stash_num: u32, paths: &[String]) {
+    let stash_name = stash_ref(stash_num);
+    if paths.is_empty() && !read_only() {
+        diff.arg(stash_name);
+    } else {
+        diff.arg(format!("{stash_name}^1")).arg(stash_name).arg("--").args(paths);
+    }
+}
+
+/// The binary files a stash changes within `paths`, which `--numstat`
+/// reports with `-` for both counts. Renames list both the old and new path.
+fn stash_binary_files(stash_num: u32, paths: &[String]) -> io::Result<Vec<String>> {
+    let mut numstat = stash_diff(paths);
+    numstat.args(["--numstat", "-z"]);
+    stash_diff_target(&mut numstat, stash_num, paths);
+    let output = numstat.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(error(&format!("failed to show {}", stash_ref(stash_num))));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split('\0');
+    let mut files = Vec::new();
+    while let Some(entry) = fields.next().filter(|f| !f.is_empty()) {
+        let mut counts = entry.splitn(3, '\t');
+        let binary = counts.next() == Some("-") && counts.next() == Some("-");
+        // A rename leaves the path empty and puts both names after it.
+        let names = match counts.next().unwrap_or_default() {
+            "" => vec![fields.next().unwrap_or_default(), fields.next().unwrap_or_default()],
+            path => vec![path],
+        };
+        if binary {
+            files.extend(names.into_iter().map(str::to_string));
+        }
+    }
+    Ok(files)
+}
+
+fn git_stash_show(stash: &Stash, args: &Args, cache: &mut StashCache) -> io::Result<()> {
+    let stash_num = stash.index;
+    // Binary files are left out of the patch and summarized after it.
+    let binary = if args.binary_summary {
+        cache.binary_files(stash, &args.paths)?.to_vec()
+    } else {
+        Vec::new()
+    };
+    let mut paths = args.paths.clone();
+    paths.extend(binary.iter().map(|path| format!(":(top,exclude,literal){path}")));
+    git_stash_show_paths(stash_num, &paths, args)?;
+    if !binary.is_empty() {
+        let Palette { clear, bold, .. } = palette(Stream::Stdout);
+        let changed = Message::BinaryFilesChanged { count: binary.len() };
+        println!("{bold}{changed}{clear} {}", binary.join(", "));
+    }
+    Ok(())
+}
+
+/// Show the stash's patch limited to `paths`, through the pager if any.
+fn git_stash_show_paths(stash_num: u32, paths: &[String], args: &Args) -> io::Result<()> {
+    let mut show = stash_diff(paths);
+    show.arg("-p");
+    if let Some(context) = args.context {
+        show.arg(format!("-U{context}"));
+    }
+    match args.word_diff.as_deref() {
+        Some("") => { show.arg("--word-diff"); }
+        Some(mode) => { show.arg(format!("--word-diff={mode}")); }
+        None => (),
+    }
+    show.arg(diff_color_arg());
+    stash_diff_target(&mut show, stash_num, paths);
+    let status = match pager()? {
+        Some(pager) => show_in_pager(show, &pager)?,
+        None => show.status()?,
+    };
+    if !exited_ok(status)? {
+        return Err(error(&format!("failed to show {}", stash_ref(stash_num))));
+    }
+    Ok(())
+}
+
+/// Run `show` with its output going through `pager`, returning how `show`
+/// exited.
+fn show_in_pager(mut show: Git, pager: &str) -> io::Result<ExitStatus> {
+    let mut less = Command::new("sh");
+    less.args(["-c", pager]).stdin(Stdio::piped());
+    // Match git: let `less` quit on short diffs and pass colors through.
+    if std::env::var_os("LESS").is_none() {
+        less.env("LESS", "FRX");
+    }
+    default_interrupts(&mut less);
+    let _ignoring = IgnoreInterrupts::new();
+    let mut child = less.spawn()?;
+    let stdin = child.stdin.take().ok_or_else(|| error("pager has no stdin"))?;
+    // `show` must be dropped so the pager sees end of input.
+    let status = show.stdout(stdin).foreground().status();
+    drop(show);
+    child.wait()?;
+    status
+}
+
+/// Whether `git difftool` has a tool to run, rather than guessing one.
+fn difftool_configured() -> io::Result<bool> {
+    for key in ["diff.tool", "merge.tool"] {
+        let output = git(["config", "--get", key]).output()?;
+        if output.status.success() && !output.stdout.trim_ascii().is_empty() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Open the stash in the user's `git difftool`, waiting for it to close.
+/// Without a configured tool this falls back to `git_stash_show`.
+fn git_stash_difftool(stash: &Stash, args: &Args, cache: &mut StashCache) -> io::Result<()> {
+    if !difftool_configured()? {
+        let Palette { clear, yellow, .. } = palette(Stream::Stderr);
+        eprintln!("{yellow}{}{clear}", Message::NoDiffTool);
+        return git_stash_show(stash, args, cache);
+    }
+    let stash_name = stash_ref(stash.index);
+    let mut difftool = git(["difftool", "--no-prompt"]);
+    difftool.arg(format!("{stash_name}^1")).arg(&stash_name);
+    if !args.paths.is_empty() {
+        difftool.arg("--").args(&args.paths);
+    }
+    if !exited_ok(difftool.foreground().status()?)? {
+        return Err(error(&format!("git difftool failed to show {stash_name}")));
+    }
+    Ok(())
+}
+
+/// What the loop has learned about each stash, so that showing one again, or
+/// asking whether it's applied right after showing it, doesn't ask git again.
+/// Keyed by hash: a stash commit never changes, so its size and binary files
+/// stay valid across drops and renames. Whether it's applied depends on the
+/// working tree, so that is forgotten after any action that touches it.
+#[derive(Default)]
+struct StashCache {
+    shortstat: HashMap<String, String>,
+    binary_files: HashMap<String, Vec<String>>,
+    applied: HashMap<String, bool>,
+}
+
+impl StashCache {
+    fn shortstat(&mut self, stash: &Stash) -> io::Result<&str> {
+        if !self.shortstat.contains_key(&stash.hash) {
+            let shortstat = git_stash_shortstat(stash.index)?;
+            self.shortstat.insert(stash.hash.clone(), shortstat);
+        }
+        Ok(&self.shortstat[&stash.hash])
+    }
+
+    /// The binary files the stash changes within `paths`, which are the same
+    /// `--path`s for the whole session.
+    fn binary_files(&mut self, stash: &Stash, paths: &[String]) -> io::Result<&[String]> {
+        if !self.binary_files.contains_key(&stash.hash) {
+            let files = stash_binary_files(stash.index, paths)?;
+            self.binary_files.insert(stash.hash.clone(), files);
+        }
+        Ok(&self.binary_files[&stash.hash])
+    }
+
+    fn is_applied(&mut self, stash: &Stash) -> io::Result<bool> {
+        if let Some(&applied) = self.applied.get(&stash.hash) {
+            return Ok(applied);
+        }
+        let applied = stash_is_applied(stash)?;
+        self.applied.insert(stash.hash.clone(), applied);
+        Ok(applied)
+    }
+
+    /// Forget which stashes are applied, after the working tree has changed.
+    fn forget_applied(&mut self) {
+        self.applied.clear();
+    }
+}
+
+/// Print the stash commit's details: its reflog entry, parents, author and
+/// committer, then the commit it was made on top of.
+fn git_stash_info(stash_num: u32) -> io::Result<()> {
+    let stash_name = stash_ref(stash_num);
+    let shown = git(["--no-pager", "log", "-g", "-1", "--parents", "--format=fuller"])
+        .arg(diff_color_arg())
+        .arg(&stash_name)
+        .status()?
+        .success();
+    if !shown {
+        return Err(error(&format!("failed to show {stash_name}")));
+    }
+    let output = git(["--no-pager", "log", "-1", "--format=%h %s", &format!("{stash_name}^1")])
+        .output()?;
+    let base = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let Palette { clear, bold, .. } = palette(Stream::Stdout);
+    println!("\n{bold}{}{clear} {base}", Message::MadeOnTopOf);
+    let untracked = git(["rev-parse", "-q", "--verify", &format!("{stash_name}^3")])
+        .stdout(Stdio::null())
+        .status()?
+        .success();
+    if untracked {
+        println!("{bold}{}{clear}", Message::IncludesUntracked);
+    }
+    Ok(())
+}
+
+/// The stash's size, like "2 files changed, 10 insertions(+), 1 deletion(-)".
+fn git_stash_shortstat(stash_num: u32) -> io::Result<String> {
+    let mut shortstat = stash_diff(&[]);
+    shortstat.arg("--shortstat");
+    stash_diff_target(&mut shortstat, stash_num, &[]);
+    let output = shortstat.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(error(&format!("failed to show {}", stash_ref(stash_num))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Print a diffstat of the stash limited to `paths`, if any.
+fn git_stash_stat(stash_num: u32, paths: &[String]) -> io::Result<()> {
+    let mut stat = stash_diff(paths);
+    stat.args(["--stat", diff_color_arg()]);
+    stash_diff_target(&mut stat, stash_num, paths);
+    if !exited_ok(stat.status()?)? {
+        return Err(error(&format!("failed to show {}", stash_ref(stash_num))));
+    }
+    Ok(())
+}
+
+/// A file a stash changes, from `--name-status`.
+struct ChangedFile {
+    /// Git's status, like "M", or "R086" for a rename with its similarity.
+    status: String,
+    path: String,
+    /// Where a rename or copy ended up.
+    new_path: Option<String>,
+}
+
+/// The files a stash changes within `paths`, or everywhere if empty.
+fn git_stash_name_status(stash_num: u32, paths: &[String]) -> io::Result<Vec<ChangedFile>> {
+    let mut name_status = stash_diff(paths);
+    name_status.args(["--name-status", "-z"]);
+    stash_diff_target(&mut name_status, stash_num, paths);
+    let output = name_status.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(error(&format!("failed to show {}", stash_ref(stash_num))));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split('\0').filter(|f| !f.is_empty());
+    let mut files = Vec::new();
+    while let Some(status) = fields.next() {
+        let path = fields.next().unwrap_or_default().to_string();
+        let new_path = match status.chars().next() {
+            Some('R' | 'C') => Some(fields.next().unwrap_or_default().to_string()),
+            _ => None,
+        };
+        files.push(ChangedFile { status: status.to_string(), path, new_path });
+    }
+    Ok(files)
+}
+
+/// List changed files with the statuses lined up in a column, and renames'
+/// arrows in another, colored as in git's diffs. `numbered` puts a number
+/// before each, to pick one by.
+fn print_name_status(files: &[ChangedFile], numbered: bool) {
+    let Palette { clear, green, red, yellow, blue, .. } = palette(Stream::Stdout);
+    let width = |text: &str| text.chars().count();
+    let status_width = files.iter().map(|f| width(&f.status)).max().unwrap_or_default();
+    let path_width = files.iter()
+        .filter(|f| f.new_path.is_some())
+        .map(|f| width(&f.path))
+        .max()
+        .unwrap_or_default();
+    let number_width = files.len().to_string().len();
+    for (i, file) in files.iter().enumerate() {
+        if numbered {
+            print!("{:>number_width$}) ", i + 1);
+        }
+        let color = match file.status.chars().next() {
+            Some('A') => green,
+            Some('D') => red,
+            Some('M') => yellow,
+            Some('R' | 'C') => blue,
+            _ => "",
+        };
+        // Padding goes outside the color codes, so it lines up either way.
+        let status_pad = " ".repeat(status_width - width(&file.status));
+        let status = format!("{color}{}{clear}{status_pad}", file.status);
+        match &file.new_path {
+            Some(new_path) => {
+                let path_pad = " ".repeat(path_width - width(&file.path));
+                println!("{status}  {}{path_pad} -> {new_path}", file.path);
+            }
+            None => println!("{status}  {}", file.path),
+        }
+    }
+}
+
+/// Whether there are no stashes, going by what `git stash list` can list
+/// rather than whether `refs/stash` exists: the ref can be left dangling
+/// without its reflog, where the stashes are kept, and then there are none.
+fn git_stashes_is_empty() -> io::Result<bool> {
+    Ok(list_stashes(None)?.is_empty())
+}
+
+/// A non-interactive operation applied to every stash.
+#[derive(Clone, Copy, PartialEq)]
+enum Batch {
+    /// Drop every stash whose changes are already applied.
+    DropApplied,
+    /// Commit every stash to its own branch.
+    BranchAll,
+    /// Commit every stash onto one new branch; see `Args::collect_branch`.
+    Collect,
+    /// Apply every stash onto the working tree, stopping at the first that
+    /// doesn't apply.
+    ApplyAll,
+}
+
+/// An alternative to the triage loop, chosen by a leading positional word.
+enum Subcommand {
+    /// Turn a patch file into a new stash.
+    Import(PathBuf),
+    /// Print the stashes and exit.
+    List,
+    /// Print a completion script for the shell and exit. Hidden, as it's for
+    /// setting up a shell rather than working with stashes.
+    Completions(completions::Shell),
+}
+
+/// Output format for the `list` subcommand.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum ListFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// What pressing Enter without typing an action does.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum DefaultAction {
+    #[default]
+    Help,
+    Skip,
+    /// Drop the stash, but always ask first.
+    Drop,
+}
+
+impl DefaultAction {
+    /// Parse a value from `source`, the flag or config key it came from.
+    fn parse(value: &str, source: &str) -> io::Result<Self> {
+        match value {
+            "help" => Ok(DefaultAction::Help),
+            "skip" => Ok(DefaultAction::Skip),
+            "drop" => Ok(DefaultAction::Drop),
+            _ => Err(error(&format!(
+                "invalid {source} value '{value}'; expected skip, help, or drop"
+            ))),
+        }
+    }
+
+    /// The action that empty input stands for.
+    fn action(self) -> Action {
+        match self {
+            DefaultAction::Help => Action::Help,
+            DefaultAction::Skip => Action::Skip,
+            DefaultAction::Drop => Action::Drop,
+        }
+    }
+}
+
+/// Something to do to the stash under review.
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Drop,
+    DropApplied,
+    Branch,
+    BranchKeep,
+    Tag,
+    Commit,
+    Skip,
+    Previous,
+    Apply,
+    ApplyOnto,
+    Pop,
+    PopIndex,
+    Export,
+    Copy,
+    Rename,
+    View,
+    File,
+    Open,
+    Info,
+    Undo,
+    Quit,
+    Help,
+}
+
+impl Action {
+    /// Every action, in the order they're listed in the prompt and help.
+    const ALL: [Action; 22] = [
+        Action::Drop,
+        Action::DropApplied,
+        Action::Branch,
+        Action::BranchKeep,
+        Action::Tag,
+        Action::Commit,
+        Action::Skip,
+        Action::Previous,
+        Action::Apply,
+        Action::ApplyOnto,
+        Action::Pop,
+        Action::PopIndex,
+        Action::Export,
+        Action::Copy,
+        Action::Rename,
+        Action::View,
+        Action::File,
+        Action::Open,
+        Action::Info,
+        Action::Undo,
+        Action::Quit,
+        Action::Help,
+    ];
+
+    /// The action's name in `stashinbox.key.<name>` config keys.
+    fn name(self) -> &'static str {
+        match self {
+            Action::Drop => "drop",
+            Action::DropApplied => "dropApplied",
+            Action::Branch => "branch",
+            Action::BranchKeep => "branchKeep",
+            Action::Tag => "tag",
+            Action::Commit => "commit",
+            Action::Skip => "skip",
+            Action::Previous => "previous",
+            Action::Apply => "apply",
+            Action::ApplyOnto => "applyOnto",
+            Action::Pop => "pop",
+            Action::PopIndex => "popIndex",
+            Action::Export => "export",
+            Action::Copy => "copy",
+            Action::Rename => "rename",
+            Action::View => "view",
+            Action::File => "file",
+            Action::Open => "open",
+            Action::Info => "info",
+            Action::Undo => "undo",
+            Action::Quit => "quit",
+            Action::Help => "help",
+        }
+    }
+
+    fn default_key(self) -> char {
+        match self {
+            Action::Drop => 'd',
+            Action::DropApplied => 'D',
+            Action::Branch => 'b',
+            Action::BranchKeep => 'B',
+            Action::Tag => 't',
+            Action::Commit => 'c',
+            Action::Skip => 's',
+            Action::Previous => 'k',
+            Action::Apply => 'a',
+            Action::ApplyOnto => 'A',
+            Action::Pop => 'p',
+            Action::PopIndex => 'P',
+            Action::Export => 'e',
+            Action::Copy => 'y',
+            Action::Rename => 'r',
+            Action::View => 'v',
+            Action::Open => 'o',
+            Action::Info => 'i',
+            Action::File => 'f',
+            Action::Undo => 'u',
+            Action::Quit => 'q',
+            Action::Help => '?',
+        }
+    }
+
+    /// Whether the action needs a work tree, or changes the stashes or
+    /// branches; none of these are offered in a bare repository.
+    fn changes_repository(self) -> bool {
+        match self {
+            Action::Drop | Action::DropApplied | Action::Branch | Action::BranchKeep
+            | Action::Tag | Action::Commit | Action::Apply | Action::ApplyOnto | Action::Pop
+            | Action::PopIndex | Action::Rename | Action::Undo => true,
+            Action::Skip | Action::Previous | Action::Export | Action::Copy | Action::View
+            | Action::File | Action::Open | Action::Info | Action::Quit | Action::Help => false,
+        }
+    }
+}
+
+/// The key bound to each action, in `Action::ALL` order.
+struct Keymap {
+    keys: [char; Action::ALL.len()],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap { keys: Action::ALL.map(Action::default_key) }
+    }
+}
+
+impl Keymap {
+    /// Read any `stashinbox.key.<action>` overrides from git config, on top
+    /// of `user_keys` from the user's config file.
+    fn load(user_keys: &[(Action, char)]) -> io::Result<Self> {
+        let mut keymap = Keymap::default();
+        for &(action, key) in user_keys {
+            let i = Action::ALL.iter().position(|&a| a == action).unwrap_or_default();
+            keymap.keys[i] = key;
+        }
+        for (i, action) in Action::ALL.into_iter().enumerate() {
+            let config_key = format!("stashinbox.key.{}", action.name());
+            let Some(value) = git_config(&config_key)? else {
+                continue;
+            };
+            // Raw mode reads a single byte per keypress.
+            let mut chars = value.chars();
+            keymap.keys[i] = match (chars.next(), chars.next()) {
+                (Some(key), None) if key.is_ascii_graphic() => key,
+                _ => return Err(error(&format!(
+                    "invalid {config_key} value '{value}'; expected a single character"
+                ))),
+            };
+        }
+        for (i, key) in keymap.keys.iter().enumerate() {
+            if let Some(j) = keymap.keys[..i].iter().position(|k| k == key) {
+                return Err(error(&format!(
+                    "stashinbox.key.{} and stashinbox.key.{} are both bound to '{key}'",
+                    Action::ALL[j].name(),
+                    Action::ALL[i].name(),
+                )));
+            }
+        }
+        Ok(keymap)
+    }
+
+    fn key(&self, action: Action) -> char {
+        let i = Action::ALL.iter().position(|&a| a == action).unwrap_or_default();
+        self.keys[i]
+    }
+
+    /// The action for typed input. An exact match wins, but otherwise case is
+    /// ignored so that caps lock doesn't get in the way, as long as that
+    /// leaves only one candidate.
+    fn action(&self, input: &str) -> Option<Action> {
+        let mut chars = input.chars();
+        let (Some(typed), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        if let Some(i) = self.keys.iter().position(|&k| k == typed) {
+            return Some(Action::ALL[i]);
+        }
+        let mut matches = Action::ALL.into_iter()
+            .filter(|&a| self.key(a).eq_ignore_ascii_case(&typed));
+        match (matches.next(), matches.next()) {
+            (Some(action), None) => Some(action),
+            _ => None,
+        }
+    }
+
+    /// The actions available right now; undo only once something was dropped,
+    /// and nothing that changes the repository in a bare one.
+    fn available(can_undo: bool) -> impl Iterator<Item = Action> {
+        Action::ALL.into_iter()
+            .filter(move |&a| can_undo || a != Action::Undo)
+            .filter(|a| !(read_only() && a.changes_repository()))
+    }
+
+    /// The comma-separated keys to show in the prompt.
+    fn prompt_keys(&self, can_undo: bool) -> String {
+        Keymap::available(can_undo)
+            .map(|a| self.key(a).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// List the same actions as the prompt, with their current keys.
+    fn print_help(&self, can_undo: bool, paths: &[String]) {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stdout);
+        for action in Keymap::available(can_undo) {
+            println!("{bold}{red}{} - {}{clear}", self.key(action), Message::ActionHelp { action });
+        }
+        if !paths.is_empty() {
+            println!("{bold}{red}{}{clear}", Message::PathsOnly { paths: &paths.join(", ") });
+        }
+    }
+}
+
+/// Command-line options.
+#[derive(Default)]
+struct Args {
+    /// Read whole lines instead of single keypresses, even on a terminal.
+    line_input: bool,
+    /// Read input from this file instead, one action or answer per line.
+    script: Option<PathBuf>,
+    /// Run a batch operation instead of prompting.
+    batch: Option<Batch>,
+    /// Branch to commit every stash onto with `--collect-branch`.
+    collect_branch: Option<String>,
+    color: Option<ColorMode>,
+    /// Show a diffstat for each stash instead of the full patch.
+    stat: bool,
+    /// Show the changed file names for each stash instead of the full patch.
+    names: bool,
+    /// Show each stash's size under its header.
+    shortstat: bool,
+    /// List every stash before starting on the first one.
+    overview: bool,
+    /// Mark stashes first, then act on all the marked ones; see
+    /// `select_stashes`.
+    select: bool,
+    /// Review stashes in the full-screen interface; see `tui::run`.
+    tui: bool,
+    /// Summarize binary files after the patch instead of showing them in it.
+    binary_summary: bool,
+    /// Only include stashes older than this many seconds.
+    older_than: Option<u64>,
+    /// Mark stashes older than this many seconds as OLD.
+    mark_older_than: Option<u64>,
+    /// Only include stashes made on or after this date, from `--since`; see
+    /// `parse_date`.
+    since: Option<String>,
+    /// Only include stashes made on or before this date, from `--until`.
+    until: Option<String>,
+    /// Index of the first stash to triage.
+    start: u32,
+    /// Seconds to wait for each response before taking the default.
+    timeout: Option<u64>,
+    /// Only include `stash@{A}` up to but not including `stash@{B}`, from
+    /// `--range A..B`. Batch operations and `list` only, since the loop
+    /// renumbers stashes as it goes.
+    range: Option<std::ops::Range<u32>>,
+    /// Prefix for rescue branch names; see `Args::branch_prefix`.
+    branch_prefix: Option<String>,
+    /// Longest subject part of a rescue branch name, in characters; see
+    /// `Args::max_branch_length`.
+    max_branch_length: Option<usize>,
+    /// Lines of context around each diff hunk, git's default when unset.
+    context: Option<u32>,
+    /// Show word-level diffs in this `--word-diff` mode, empty for git's
+    /// default.
+    word_diff: Option<String>,
+    /// Restore staged changes as staged when applying or popping. This fails
+    /// when the index has moved on since the stash was made, in which case
+    /// everything is applied unstaged as usual.
+    index: bool,
+    /// Sign rescue commits with this GPG key, or the default key if empty.
+    sign: Option<String>,
+    /// Ask before quitting with stashes left to review.
+    confirm_quit: bool,
+    /// Date rescue commits now, rather than when the stash was made.
+    current_date: bool,
+    /// Credit rescue commits to whoever made the stash, not the current user.
+    stash_author: bool,
+    /// Start each batch's rescue commit subjects with `[N/COUNT]`, counting
+    /// from the oldest stash; see `batch_subject`.
+    number_commits: bool,
+    /// Offer each rescue branch's name for editing before it's given; see
+    /// `prompt_branch_name`.
+    edit_branch_name: bool,
+    /// Open the view action in `git difftool` instead of the pager.
+    difftool: bool,
+    /// Run commit hooks when committing stashes, from `--verify`,
+    /// `--no-verify` or `stashinbox.verify`.
+    verify: Option<bool>,
+    /// Stash local changes away while committing stashes to branches.
+    autostash: bool,
+    /// Commit stashes to branches in a temporary worktree.
+    worktree: bool,
+    /// Print mutating git commands instead of running them.
+    dry_run: bool,
+    /// How much to log about git commands; see `VERBOSE`.
+    verbose: u8,
+    /// Suppress stash headers, help on empty input, and status messages.
+    quiet: bool,
+    /// Print a line for each action taken, for programs driving the session;
+    /// see `print_event`.
+    porcelain: bool,
+    /// Append a timestamped line for each action taken to this file; see
+    /// `open_log`.
+    log: Option<PathBuf>,
+    /// Drop stashes without asking whether they were applied.
+    force: bool,
+    /// Only include stashes whose message matches this extended regex.
+    grep: Option<String>,
+    /// What empty input does; see `Args::default_action`.
+    default_action: Option<DefaultAction>,
+    keymap: Keymap,
+    /// When to color output if neither `--color` nor git config says, from
+    /// the user's config file.
+    color_fallback: Option<ColorMode>,
+    subcommand: Option<Subcommand>,
+    format: ListFormat,
+    /// Pathspecs after `--`, limiting the diffs shown but not the actions.
+    paths: Vec<String>,
+}
+
+/// Give a non-empty prefix exactly one trailing slash and check that it can
+/// start a branch name.
+fn normalize_branch_prefix(prefix: &str) -> io::Result<String> {
+    let trimmed = prefix.trim().trim_end_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    let prefix = format!("{trimmed}/");
+    let valid = git(["check-ref-format", &format!("refs/heads/{prefix}x")])
+        .status()?
+        .success();
+    if !valid {
+        return Err(error(&format!("invalid branch prefix '{prefix}'")));
+    }
+    Ok(prefix)
+}
+
+/// Parse an age like `12h`, `30d`, `2w`, `6m` or `1y` into seconds. Months
+/// are 30 days and years 365.
+fn parse_age(flag: &str, value: &str) -> io::Result<u64> {
+    const DAY: u64 = 24 * 60 * 60;
+    let invalid = || error(&format!(
+        "invalid {flag} '{value}'; expected a number followed by h, d, w, m, or y"
+    ));
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let count: u64 = value[..value.len() - unit.len_utf8()].parse().map_err(|_| invalid())?;
+    let unit = match unit {
+        'h' => 60 * 60,
+        'd' => DAY,
+        'w' => 7 * DAY,
+        'm' => 30 * DAY,
+        'y' => 365 * DAY,
+        _ => return Err(invalid()),
+    };
+    count.checked_mul(unit).ok_or_else(invalid)
+}
+
+/// Check an ISO date like `2024-03-01`, optionally with a time like
+/// `2024-03-01T14:30` or `2024-03-01 14:30:00`, and return it with a `T`
+/// between the two. Each bound covers the whole day, minute or second it
+/// names, and is compared with the stash's date in its own time zone, as
+/// `list` shows it.
+fn parse_date(flag: &str, value: &str) -> io::Result<String> {
+    let invalid = || error(&format!(
+        "invalid {flag} '{value}'; expected a date like 2024-03-01 or 2024-03-01T14:30"
+    ));
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    // Each field must be two digits, or four for the year, within `max`.
+    let field = |text: &str, len: usize, min: u32, max: u32| {
+        text.len() == len
+            && text.bytes().all(|b| b.is_ascii_digit())
+            && text.parse().is_ok_and(|n: u32| (min..=max).contains(&n))
+    };
+    let parts: Vec<&str> = date.split('-').collect();
+    let valid = matches!(parts[..], [year, month, day]
+        if field(year, 4, 0, 9999) && field(month, 2, 1, 12) && field(day, 2, 1, 31));
+    if !valid {
+        return Err(invalid());
+    }
+    let Some(time) = time else {
+        return Ok(date.to_string());
+    };
+    let parts: Vec<&str> = time.split(':').collect();
+    let valid = match parts[..] {
+        [hour, minute] => field(hour, 2, 0, 23) && field(minute, 2, 0, 59),
+        [hour, minute, second] => {
+            field(hour, 2, 0, 23) && field(minute, 2, 0, 59) && field(second, 2, 0, 60)
+        }
+        _ => false,
+    };
+    if !valid {
+        return Err(invalid());
+    }
+    Ok(format!("{date}T{time}"))
+}
+
+/// Parse a half-open range of stash indices like `3..6`.
+fn parse_range(value: &str) -> io::Result<std::ops::Range<u32>> {
+    let invalid = || error(&format!(
+        "invalid --range '{value}'; expected A..B, from stash@{{A}} up to but not including B"
+    ));
+    let (start, end) = value.split_once("..").ok_or_else(invalid)?;
+    let range = start.parse().map_err(|_| invalid())?..end.parse().map_err(|_| invalid())?;
+    if range.is_empty() {
+        return Err(error(&format!("--range {value} selects no stashes")));
+    }
+    Ok(range)
+}
+
+fn parse_context(value: &str) -> io::Result<u32> {
+    value.parse().map_err(|_| {
+        error(&format!("invalid context '{value}'; expected a non-negative integer"))
+    })
+}
+
+const DEFAULT_BRANCH_PREFIX: &str = "stash/";
+
+impl Args {
+    /// The settings used when nothing is given on the command line.
+    fn new() -> Self {
+        Args {
+            shortstat: true,
+            binary_summary: true,
+            ..Args::default()
+        }
+    }
+
+    /// Fill in settings not given on the command line from git config, and
+    /// failing that the user's config file.
+    fn load_config(&mut self) -> io::Result<()> {
+        let user = UserConfig::load()?;
+        if self.branch_prefix.is_none() {
+            self.branch_prefix = git_config("stashinbox.branchPrefix")?.or(user.branch_prefix);
+        }
+        if let Some(prefix) = &mut self.branch_prefix {
+            *prefix = normalize_branch_prefix(prefix)?;
+        }
+        if self.default_action.is_none() {
+            if let Some(value) = git_config("stashinbox.defaultAction")? {
+                let value = value.to_lowercase();
+                self.default_action =
+                    Some(DefaultAction::parse(&value, "stashinbox.defaultAction")?);
+            }
+        }
+        self.default_action = self.default_action.or(user.default_action);
+        if self.verify.is_none() {
+            self.verify = git_config_bool("stashinbox.verify")?.or(user.verify);
+        }
+        self.max_branch_length = self.max_branch_length.or(user.max_branch_length);
+        self.color_fallback = user.color;
+        self.keymap = Keymap::load(&user.keys)?;
+        Ok(())
+    }
+
+    fn max_branch_length(&self) -> usize {
+        self.max_branch_length.unwrap_or(DEFAULT_MAX_BRANCH_LENGTH)
+    }
+
+    /// What empty input does, showing help unless configured otherwise.
+    fn default_action(&self) -> DefaultAction {
+        self.default_action.unwrap_or_default()
+    }
+
+    /// The prefix for rescue branch names: empty, or ending in a single `/`.
+    fn branch_prefix(&self) -> &str {
+        self.branch_prefix.as_deref().unwrap_or(DEFAULT_BRANCH_PREFIX)
+    }
+
+    fn set_batch(&mut self, batch: Batch) -> io::Result<()> {
+        if self.batch.is_some_and(|b| b != batch) {
+            return Err(error(
+                "only one of --drop-applied, --branch-all, --collect-branch and --apply-all \
+                may be given"
+            ));
+        }
+        self.batch = Some(batch);
+        Ok(())
+    }
+}
+
+const DEFAULT_MAX_BRANCH_LENGTH: usize = 50;
+
+fn parse_args() -> io::Result<Args> {
+    let mut args = Args::new();
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        // Accept both `--flag value` and `--flag=value`.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline.map(str::to_string)
+                .or_else(|| argv.next())
+                .ok_or_else(|| error(&format!("{flag} requires a value")))
+        };
+        match flag {
+            "--line-input" => args.line_input = true,
+            "--stat" => args.stat = true,
+            "--names" => args.names = true,
+            "--no-shortstat" => args.shortstat = false,
+            "--overview" => args.overview = true,
+            "--select" => args.select = true,
+            "--tui" => args.tui = true,
+            "--no-binary-summary" => args.binary_summary = false,
+            "--quiet" | "-q" => args.quiet = true,
+            "--porcelain" => args.porcelain = true,
+            "--force" | "-f" => args.force = true,
+            "--dry-run" | "-n" => args.dry_run = true,
+            "--worktree" => args.worktree = true,
+            "--autostash" => args.autostash = true,
+            "--verify" => args.verify = Some(true),
+            "--no-verify" => args.verify = Some(false),
+            "--difftool" => args.difftool = true,
+            "--current-date" => args.current_date = true,
+            "--stash-author" => args.stash_author = true,
+            "--number-commits" => args.number_commits = true,
+            "--edit-branch-name" => args.edit_branch_name = true,
+            "--index" => args.index = true,
+            "--confirm-quit" => args.confirm_quit = true,
+            "--verbose" => args.verbose = args.verbose.saturating_add(1),
+            // `-v`, `-vv`, and so on.
+            _ if flag.starts_with("-v") && flag[1..].bytes().all(|b| b == b'v') => {
+                let count = u8::try_from(flag.len() - 1).unwrap_or(u8::MAX);
+                args.verbose = args.verbose.saturating_add(count);
+            }
+            "--sign" | "-S" => args.sign = Some(inline.unwrap_or_default().to_string()),
+            _ if flag.starts_with("-S") => args.sign = Some(flag[2..].to_string()),
+            "--color" => {
+                args.color = Some(match inline {
+                    Some(value) => ColorMode::parse(value)?,
+                    None => ColorMode::Always,
+                });
+            }
+            "--grep" => args.grep = Some(value()?),
+            "--script" => args.script = Some(value()?.into()),
+            "--log" => args.log = Some(value()?.into()),
+            "--older-than" => args.older_than = Some(parse_age(flag, &value()?)?),
+            "--mark-older-than" => args.mark_older_than = Some(parse_age(flag, &value()?)?),
+            "--since" => args.since = Some(parse_date(flag, &value()?)?),
+            "--until" => args.until = Some(parse_date(flag, &value()?)?),
+            "--default-action" => {
+                args.default_action = Some(DefaultAction::parse(&value()?, flag)?);
+            }
+            "--branch-prefix" => args.branch_prefix = Some(value()?),
+            "--max-branch-length" => {
+                let value = value()?;
+                args.max_branch_length = match value.parse() {
+                    Ok(max) if max > 0 => Some(max),
+                    _ => return Err(error(&format!(
+                        "invalid --max-branch-length '{value}'; expected a positive integer"
+                    ))),
+                };
+            }
+            "--start" => {
+                let value = value()?;
+                args.start = value.parse().map_err(|_| {
+                    error(&format!("invalid --start '{value}'; expected a stash index"))
+                })?;
+            }
+            "--word-diff" => {
+                let mode = inline.unwrap_or_default();
+                if !matches!(mode, "" | "color" | "plain" | "porcelain" | "none") {
+                    return Err(error(&format!(
+                        "invalid --word-diff mode '{mode}'; expected color, plain, porcelain, or none"
+                    )));
+                }
+                args.word_diff = Some(mode.to_string());
+            }
+            "--context" | "-U" => args.context = Some(parse_context(&value()?)?),
+            _ if flag.starts_with("-U") => args.context = Some(parse_context(&flag[2..])?),
+            "--drop-applied" => args.set_batch(Batch::DropApplied)?,
+            "--branch-all" => args.set_batch(Batch::BranchAll)?,
+            "--apply-all" => args.set_batch(Batch::ApplyAll)?,
+            "--collect-branch" => {
+                args.collect_branch = Some(value()?);
+                args.set_batch(Batch::Collect)?;
+            }
+            "--format" => {
+                args.format = match value()?.as_str() {
+                    "text" => ListFormat::Text,
+                    "json" => ListFormat::Json,
+                    other => return Err(error(&format!(
+                        "invalid --format '{other}'; expected text or json"
+                    ))),
+                };
+            }
+            "--range" => args.range = Some(parse_range(&value()?)?),
+            "--timeout" => {
+                let value = value()?;
+                let secs = value.parse().ok().filter(|&secs| secs > 0).ok_or_else(|| {
+                    error(&format!("invalid --timeout '{value}'; expected a number of seconds"))
+                })?;
+                args.timeout = Some(secs);
+            }
+            "--" => args.paths.extend(argv.by_ref()),
+            "list" if args.subcommand.is_none() => args.subcommand = Some(Subcommand::List),
+            "completions" if args.subcommand.is_none() => {
+                let shell = argv.next()
+                    .ok_or_else(|| error("completions requires a shell: bash, zsh, or fish"))?;
+                args.subcommand = Some(Subcommand::Completions(completions::Shell::parse(&shell)?));
+            }
+            "import" if args.subcommand.is_none() => {
+                let path = argv.next()
+                    .ok_or_else(|| error("import requires a patch file"))?;
+                args.subcommand = Some(Subcommand::Import(path.into()));
+            }
+            _ => return Err(error(&format!("unknown argument: {arg}"))),
+        }
+    }
+    if args.tui && (args.script.is_some() || args.select) {
+        return Err(error("--tui can't be combined with --script or --select"));
+    }
+    if let (Some(since), Some(until)) = (&args.since, &args.until) {
+        let len = since.len().min(until.len());
+        if since[..len] > until[..len] {
+            return Err(error(&format!("--since {since} is after --until {until}")));
+        }
+    }
+    if args.porcelain {
+        if args.tui {
+            return Err(error("--porcelain can't be combined with --tui"));
+        }
+        if args.color.is_some_and(|color| color != ColorMode::Never) {
+            return Err(error("--porcelain output is never colored; leave out --color"));
+        }
+        if args.subcommand.is_some() {
+            return Err(error("--porcelain only works when triaging stashes"));
+        }
+        args.color = Some(ColorMode::Never);
+    }
+    if args.log.is_some() && args.subcommand.is_some() {
+        return Err(error("--log only works when triaging stashes"));
+    }
+    let branching = matches!(args.batch, Some(Batch::BranchAll | Batch::Collect));
+    if args.number_commits && !branching {
+        return Err(error("--number-commits only works with --branch-all and --collect-branch"));
+    }
+    if args.edit_branch_name && args.batch.is_some() {
+        return Err(error("--edit-branch-name asks for each name, so it can't be used in a batch"));
+    }
+    let listing = matches!(args.subcommand, Some(Subcommand::List));
+    if args.range.is_some() && args.batch.is_none() && !listing {
+        return Err(error("--range only works with batch operations and list"));
+    }
+    Ok(args)
+}
+
+fn stty<I, S>(args: I) -> io::Result<String>
+where I: IntoIterator<Item = S>,
+      S: AsRef<OsStr>
+{
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(error("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Puts the terminal into non-canonical, no-echo mode until dropped. Ctrl-C
+/// is delivered as a keypress instead of a signal while this is active.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(["-g"])?;
+        stty(["-icanon", "-echo", "-isig", "min", "1"])?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty([&self.saved]);
+    }
+}
+
+/// Lines from `--script`, read in place of the terminal.
+struct Script {
+    lines: std::vec::IntoIter<String>,
+    /// The 1-based number of the line last read.
+    line: usize,
+}
+
+static SCRIPT: OnceLock<Mutex<Script>> = OnceLock::new();
+
+fn load_script(path: &Path) -> io::Result<()> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        error(&format!("can't read script {}: {e}", path.display()))
+    })?;
+    let lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let _ = SCRIPT.set(Mutex::new(Script { lines: lines.into_iter(), line: 0 }));
+    Ok(())
+}
+
+/// The next line of the script, echoed as though typed, or `None` when there
+/// is no script. Running out of lines reads as end of input.
+fn next_script_line() -> Option<io::Result<String>> {
+    let mut script = SCRIPT.get()?.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(line) = script.lines.next() else {
+        return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+    };
+    script.line += 1;
+    println!("{line}");
+    Some(Ok(line))
+}
+
+/// The number of the script line last read, if input is scripted.
+fn script_line_number() -> Option<usize> {
+    SCRIPT.get().map(|script| script.lock().unwrap_or_else(|e| e.into_inner()).line)
+}
+
+/// Read a line of input, without its line ending. `lines` only strips a `\r`
+/// that comes right before the `\n`, leaving one on a last line with no
+/// newline, as some Windows pipes send. Any other whitespace is the caller's
+/// to trim, since a line of spaces answers `--select` as the space key does.
+fn read_line() -> io::Result<String> {
+    // Only the latest read says whether it timed out, whoever asked.
+    TIMED_OUT.store(false, Ordering::Relaxed);
+    let line = match next_script_line() {
+        Some(line) => line?,
+        None => read_stdin_line()?,
+    };
+    Ok(line.strip_suffix('\r').unwrap_or(&line).to_string())
+}
+
+fn read_stdin_line() -> io::Result<String> {
+    #[cfg(unix)]
+    {
+        if timeout().is_some() {
+            return read_line_timed();
+        }
+    }
+    io::stdin().lock().lines().next()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?
+}
+
+/// Seconds to wait for each response, from `--timeout`; 0 waits forever.
+static TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+/// Set when the last read gave up waiting, so the caller can say what it did
+/// instead. Every read clears it first.
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+fn timeout() -> Option<u64> {
+    Some(TIMEOUT.load(Ordering::Relaxed)).filter(|&secs| secs > 0)
+}
+
+/// End the prompt after a read timed out, which then reads as empty input.
+fn note_timeout() {
+    TIMED_OUT.store(true, Ordering::Relaxed);
+    println!();
+    println!("{}", Message::TimedOut { secs: timeout().unwrap_or_default() });
+}
+
+/// Wait up to `--timeout` for input on stdin, returning false if none came.
+#[cfg(unix)]
+fn wait_for_input() -> io::Result<bool> {
+    let Some(secs) = timeout() else {
+        return Ok(true);
+    };
+    let ms = i32::try_from(secs.saturating_mul(1000)).unwrap_or(i32::MAX);
+    let mut fd = sys::PollFd { fd: 0, events: sys::POLLIN, revents: 0 };
+    match unsafe { sys::poll(&mut fd, 1, ms) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+/// Read one byte straight from stdin, or `None` at end of input. This skips
+/// `Stdin`'s buffer, whose contents `wait_for_input` couldn't see.
+#[cfg(unix)]
+fn read_stdin_byte() -> io::Result<Option<u8>> {
+    let mut byte = 0u8;
+    match unsafe { sys::read(0, (&mut byte as *mut u8).cast(), 1) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ => Ok(Some(byte)),
+    }
+}
+
+/// Read a line once one starts arriving within `--timeout`, or give up and
+/// return an empty one.
+#[cfg(unix)]
+fn read_line_timed() -> io::Result<String> {
+    if !wait_for_input()? {
+        note_timeout();
+        return Ok(String::new());
+    }
+    let mut line = Vec::new();
+    loop {
+        match read_stdin_byte()? {
+            None if line.is_empty() => {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            None | Some(b'\n') => break,
+            Some(byte) => line.push(byte),
+        }
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Read a single-key response, falling back to `read_line` when stdin is
+/// not a terminal or line input was requested.
+fn read_key(args: &Args) -> io::Result<String> {
+    // Raw mode relies on `stty`, so other platforms always read lines.
+    if SCRIPT.get().is_some() || args.line_input || !cfg!(unix) || !io::stdin().is_terminal() {
+        return read_line();
+    }
+    TIMED_OUT.store(false, Ordering::Relaxed);
+    let eof = || io::Error::from(io::ErrorKind::UnexpectedEof);
+    // `None` if nothing was typed within `--timeout`.
+    let byte = {
+        let _raw = RawMode::enable()?;
+        #[cfg(unix)]
+        {
+            match wait_for_input()? {
+                true => Some(read_stdin_byte()?.ok_or_else(eof)?),
+                false => None,
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            use std::io::Read;
+            let mut buf = [0; 1];
+            if io::stdin().lock().read(&mut buf)? == 0 {
+                return Err(eof());
+            }
+            Some(buf[0])
+        }
+    };
+    let Some(byte) = byte else {
+        note_timeout();
+        return Ok(String::new());
+    };
+    let key = match byte {
+        // Ctrl-C
+        3 => interrupt(),
+        // Ctrl-D
+        4 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        b'\n' | b'\r' => String::new(),
+        byte => char::from(byte).to_string(),
+    };
+    println!("{key}");
+    Ok(key)
+}
+
+/// Whether a response to a `[y/N]` prompt is affirmative.
+fn is_yes(response: &str) -> bool {
+    matches!(response.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Whether the stash's changes are already present in the working tree,
+/// i.e. its patch can be cleanly reversed on top of it, and any untracked
+/// files it holds are there too.
+pub fn stash_is_applied(stash: &Stash) -> io::Result<bool> {
+    // Without a work tree, there's nowhere for it to be applied.
+    if read_only() || !untracked_files_present(stash)? {
+        return Ok(false);
+    }
+    let diff = git(["diff", "--binary", &format!("{}^1", stash.hash), &stash.hash])
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !diff.status.success() {
+        return Err(error(&format!("failed to diff {}", stash_ref(stash.index))));
+    }
+    if diff.stdout.is_empty() {
+        return Ok(true);
+    }
+    let mut check = git(["apply", "--reverse", "--check"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    check.stdin.take()
+        .ok_or_else(|| error("git apply has no stdin"))?
+        .write_all(&diff.stdout)?;
+    Ok(check.wait()?.success())
+}
+
+/// Whether every file in the stash's untracked parent, if it has one, is in
+/// the work tree with the same content. The patch from the stash's base
+/// leaves them out, so a stash of only untracked files would otherwise look
+/// applied however the work tree is.
+fn untracked_files_present(stash: &Stash) -> io::Result<bool> {
+    let untracked = format!("{}^3", stash.hash);
+    let has_untracked = git(["rev-parse", "-q", "--verify", &untracked])
+        .stdout(Stdio::null())
+        .status()?
+        .success();
+    if !has_untracked {
+        return Ok(true);
+    }
+    let tree = git(["ls-tree", "-r", "-z", "--full-tree", &untracked])
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !tree.status.success() {
+        return Err(error(&format!("failed to list the untracked files in {untracked}")));
+    }
+    let top = git(["rev-parse", "--show-toplevel"]).output()?;
+    let top = PathBuf::from(String::from_utf8_lossy(&top.stdout).trim());
+    let tree = String::from_utf8_lossy(&tree.stdout);
+    let mut paths = String::new();
+    let mut expected = Vec::new();
+    // Each entry is `<mode> <type> <hash>\t<path>`.
+    for entry in tree.split('\0').filter(|entry| !entry.is_empty()) {
+        let Some((info, path)) = entry.split_once('\t') else {
+            continue;
+        };
+        if top.join(path).symlink_metadata().is_err() {
+            return Ok(false);
+        }
+        paths.push_str(path);
+        paths.push('\n');
+        expected.push(info.rsplit(' ').next().unwrap_or_default());
+    }
+    if expected.is_empty() {
+        return Ok(true);
+    }
+    let mut hash = git(["hash-object", "--stdin-paths"])
+        .current_dir(&top)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    hash.stdin.take()
+        .ok_or_else(|| error("git hash-object has no stdin"))?
+        .write_all(paths.as_bytes())?;
+    let output = hash.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(false);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().eq(expected))
+}
+
+/// Drop a stash without asking, returning whether git did so.
+pub fn drop_stash(stash: &Stash) -> io::Result<bool> {
+    Ok(git_mut(["stash", "drop", &stash_ref(stash.index)]).status()?.success())
+}
+
+/// Drop a stash, confirming first if it doesn't look applied, unless
+/// `--force` is given, or if `always_confirm` is set. Returns whether the
+/// stash was dropped.
+fn prompt_drop(
+    stash: &Stash,
+    always_confirm: bool,
+    args: &Args,
+    cache: &mut StashCache,
+) -> io::Result<bool> {
+    let stash_name = stash_ref(stash.index);
+    let question = if !args.force && !cache.is_applied(stash)? {
+        Some(Message::DropUnapplied { stash: &stash_name })
+    } else if always_confirm {
+        Some(Message::DropConfirm)
+    } else {
+        None
+    };
+    if let Some(question) = question {
+        print!("{question} ");
+        io::stdout().flush()?;
+        if !is_yes(&read_key(args)?) {
+            return Ok(false);
+        }
+    }
+    let dropped = drop_stash(stash)?;
+    if !dropped {
+        record_failure();
+    }
+    Ok(dropped)
+}
+
+/// Re-create a dropped stash as `stash@{0}`.
+fn restore_stash(hash: &str, message: &str) -> io::Result<()> {
+    if !git_mut(["stash", "store", "-m", message, hash]).status()?.success() {
+        return Err(error(&format!("failed to restore stash {hash}")));
+    }
+    Ok(())
+}
+
+/// Replace a stash's message, keeping its position in the stash list.
+fn rename_stash(stash: &Stash, message: &str) -> io::Result<()> {
+    // `git stash store` can only push onto the top of the list, so the stash
+    // and every newer one are dropped and then stored again in order.
+    let newer: Vec<Stash> = list_stashes(None)?
+        .into_iter()
+        .take_while(|s| s.index <= stash.index)
+        .collect();
+    if newer.last().map(|s| &s.hash) != Some(&stash.hash) {
+        return Err(error("stash list changed unexpectedly; not renaming"));
+    }
+    // (hash, message) of each, as it should be stored again, oldest first.
+    let entries: Vec<(&str, &str)> = newer.iter().rev()
+        .map(|s| {
+            let message = if s.index == stash.index { message } else { &s.message };
+            (s.hash.as_str(), message)
+        })
+        .collect();
+    for dropped in 0..entries.len() {
+        if !git_mut(["stash", "drop", "-q", &stash_ref(0)]).status()?.success() {
+            let lost = &entries[entries.len() - dropped..];
+            return Err(rename_failed("failed to drop a stash while renaming", lost));
+        }
+    }
+    for (stored, &(hash, message)) in entries.iter().enumerate() {
+        if let Err(e) = restore_stash(hash, message) {
+            return Err(rename_failed(&e.to_string(), &entries[stored..]));
+        }
+    }
+    Ok(())
+}
+
+/// The error for a rename that stopped partway with the `lost` stashes, as
+/// (hash, message) oldest first, out of the list, saying how to put them back.
+fn rename_failed(what: &str, lost: &[(&str, &str)]) -> io::Error {
+    let mut text = what.to_string();
+    for (i, (hash, message)) in lost.iter().enumerate() {
+        if i == 0 {
+            text.push_str("; to put back the stashes taken out, run in order:");
+        }
+        let message = message.replace('\'', "'\\''");
+        text.push_str(&format!("\n  git stash store -m '{message}' {hash}"));
+    }
+    error(&text)
+}
+
+/// List the files the stash changes, then show the one picked, either as the
+/// stash has it or as its part of the stash's diff. An empty answer to
+/// either question shows nothing.
+fn prompt_file(stash: &Stash, args: &Args) -> io::Result<()> {
+    let files = git_stash_name_status(stash.index, &args.paths)?;
+    if files.is_empty() {
+        println!("{}", Message::NoChangedFiles);
+        return Ok(());
+    }
+    print_name_status(&files, true);
+    print!("{} ", Message::PickFile { count: files.len() });
+    io::stdout().flush()?;
+    let answer = read_line()?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(());
+    }
+    let Some(file) = answer.parse::<usize>().ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| files.get(i))
+    else {
+        println!("{}", Message::NoSuchFile { input: answer, count: files.len() });
+        return Ok(());
+    };
+    // Renames and copies are shown where they ended up.
+    let path = file.new_path.as_ref().unwrap_or(&file.path);
+    print!("{} ", Message::ContentOrDiff);
+    io::stdout().flush()?;
+    match read_key(args)?.trim() {
+        "c" if file.status.starts_with('D') => println!("{}", Message::DeletedInStash { path }),
+        "c" => show_stash_file(stash.index, path)?,
+        "d" => {
+            let mut paths = vec![format!(":(top,literal){}", file.path)];
+            if let Some(new_path) = &file.new_path {
+                paths.push(format!(":(top,literal){new_path}"));
+            }
+            git_stash_show_paths(stash.index, &paths, args)?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Print `path` as it is in the stash, through the pager.
+fn show_stash_file(stash_num: u32, path: &str) -> io::Result<()> {
+    let stash_name = stash_ref(stash_num);
+    // `:path` is from the top of the tree, as `--name-status` paths are.
+    let mut show = git(["--no-pager", "show", &format!("{stash_name}:{path}")]);
+    let status = match pager()? {
+        Some(pager) => show_in_pager(show, &pager)?,
+        None => show.status()?,
+    };
+    if !exited_ok(status)? {
+        return Err(error(&format!("failed to show {path} in {stash_name}")));
+    }
+    Ok(())
+}
+
+/// Ask for a new message for the stash; an empty answer keeps the old one.
+fn prompt_rename(stash: &Stash) -> io::Result<()> {
+    print!("{} ", Message::RenamePrompt { current: stash.subject() });
+    io::stdout().flush()?;
+    let answer = read_line()?;
+    let subject = answer.trim();
+    if subject.is_empty() || subject == stash.subject() {
+        return Ok(());
+    }
+    rename_stash(stash, &stash.with_subject(subject))
+}
+
+fn porcelain_status() -> io::Result<Vec<u8>> {
+    Ok(git(["status", "--porcelain"]).output()?.stdout)
+}
+
+/// Run `git stash apply` or `git stash pop` on a stash, returning whether it
+/// succeeded. With `index`, the stash's staged changes are restored as staged;
+/// if git can't do that it refuses without touching anything, so warn and
+/// retry without `--index`.
+fn unstash(verb: &str, stash_name: &str, index: bool) -> io::Result<bool> {
+    if !index {
+        return Ok(git_mut(["stash", verb, stash_name]).status()?.success());
+    }
+    let before = porcelain_status()?;
+    if git_mut(["stash", verb, "--index", stash_name]).status()?.success() {
+        return Ok(true);
+    }
+    // A conflict partway through the apply leaves changes behind; retrying
+    // on top of those would only make a mess.
+    if porcelain_status()? != before {
+        return Ok(false);
+    }
+    let Palette { clear, bold, yellow, .. } = palette(Stream::Stderr);
+    eprintln!("{bold}{yellow}{}{clear}", Message::IndexNotRestored { stash: stash_name });
+    Ok(git_mut(["stash", verb, stash_name]).status()?.success())
+}
+
+/// Paths with unresolved merge conflicts.
+fn conflicted_paths() -> io::Result<Vec<String>> {
+    let output = git(["diff", "--name-only", "--diff-filter=U", "-z"]).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// After an apply or pop that failed, say how to deal with any conflicts it
+/// left and offer to undo it. Returns false if there were no conflicts, and
+/// so nothing to explain.
+fn explain_conflicts(stash_name: &str, kept: bool, args: &Args) -> io::Result<bool> {
+    let conflicts = conflicted_paths()?;
+    if conflicts.is_empty() {
+        return Ok(false);
+    }
+    let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+    eprintln!("{bold}{red}{}{clear}", Message::Conflict { stash: stash_name });
+    for path in &conflicts {
+        eprintln!("    {path}");
+    }
+    // `git checkout .` refuses to touch unmerged paths, while `git reset
+    // --merge` undoes the merge and leaves any other local changes alone.
+    eprintln!("{}", Message::ConflictHelp { stash: stash_name, kept });
+    print!("{} ", Message::UndoConflicts);
+    io::stdout().flush()?;
+    match read_key(args) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => println!(),
+        result => {
+            if is_yes(&result?) {
+                git_mut(["reset", "--merge"]).status()?;
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Apply a stash, returning whether it applied cleanly.
+fn apply_stash(stash_num: u32, args: &Args) -> io::Result<bool> {
+    let stash_name = stash_ref(stash_num);
+    let applied = unstash("apply", &stash_name, args.index)?;
+    if !applied {
+        record_failure();
+    }
+    if !applied && !explain_conflicts(&stash_name, false, args)? {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::ApplyFailed { stash: &stash_name });
+    }
+    Ok(applied)
+}
+
+/// Switch to a branch the user names and apply the stash there, for stashes
+/// made on the wrong branch. Switches back if the apply fails without leaving
+/// anything behind, say because its conflicts were undone. Returns whether
+/// the stash applied cleanly.
+fn apply_onto_branch(stash: &Stash, args: &Args) -> io::Result<bool> {
+    let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+    if !args.autostash && has_local_changes()? {
+        eprintln!("{bold}{red}{}{clear}", Message::SwitchWithLocalChanges);
+        record_failure();
+        return Ok(false);
+    }
+    print!("{} ", Message::ApplyOntoBranch);
+    io::stdout().flush()?;
+    let answer = read_line()?;
+    let branch = answer.trim();
+    if branch.is_empty() {
+        return Ok(false);
+    }
+    if !branch_exists(branch)? {
+        eprintln!("{bold}{red}{}{clear}", Message::NoSuchBranch { branch });
+        record_failure();
+        return Ok(false);
+    }
+    let switch = || {
+        let head = Head::current()?;
+        if !git_mut(["checkout", "-q", branch]).status()?.success() {
+            return Err(error(&format!("failed to switch to {branch}")));
+        }
+        let applied = apply_stash(stash.index, args)?;
+        if !applied && !has_local_changes()? {
+            println!("{}", Message::NothingApplied);
+            head.restore()?;
+        }
+        Ok(applied)
+    };
+    if args.autostash {
+        with_autostash(switch)
+    } else {
+        switch()
+    }
+}
+
+/// Apply and drop a stash, returning whether it applied cleanly. With
+/// `index`, or `--index`, its staged changes are staged again; see `unstash`.
+fn pop_stash(stash_num: u32, index: bool, args: &Args) -> io::Result<bool> {
+    let stash_name = stash_ref(stash_num);
+    // `git stash pop` keeps the stash around when the apply conflicts.
+    let popped = unstash("pop", &stash_name, index || args.index)?;
+    if !popped {
+        record_failure();
+    }
+    if !popped && !explain_conflicts(&stash_name, true, args)? {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::PopFailed { stash: &stash_name });
+    }
+    Ok(popped)
+}
+
+/// Whether applying the stash onto HEAD would change anything.
+fn stash_changes_head(stash_num: u32) -> io::Result<bool> {
+    let stash_name = stash_ref(stash_num);
+    let same_tree = git(["diff", "--quiet", "HEAD", &stash_name]).status()?.success();
+    // Stashes made with --include-untracked keep those files in a third parent.
+    let has_untracked = git(["rev-parse", "-q", "--verify", &format!("{stash_name}^3")])
+        .stdout(Stdio::null())
+        .status()?
+        .success();
+    Ok(!same_tree || has_untracked)
+}
+
+/// What HEAD pointed at before we moved it.
+enum Head {
+    Branch(String),
+    Detached(String),
+}
+
+impl Head {
+    fn current() -> io::Result<Self> {
+        let output = git(["symbolic-ref", "-q", "--short", "HEAD"]).output()?;
+        if output.status.success() {
+            let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            return Ok(Head::Branch(branch));
+        }
+        let output = git(["rev-parse", "--verify", "HEAD"]).output()?;
+        if !output.status.success() {
+            return Err(error("could not resolve HEAD"));
+        }
+        let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Head::Detached(hash))
+    }
+
+    fn restore(&self) -> io::Result<()> {
+        let (restored, how) = match self {
+            Head::Branch(branch) => (
+                git_mut(["checkout", branch]).status()?.success(),
+                format!("git checkout {branch}"),
+            ),
+            Head::Detached(hash) => (
+                git_mut(["checkout", "--detach", hash]).status()?.success(),
+                format!("git checkout --detach {hash}"),
+            ),
+        };
+        if !restored {
+            return Err(error(&format!(
+                "couldn't switch back to where HEAD was; run `{how}` to return there"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The first line of a commit message that isn't blank or a `#` comment.
+fn first_commit_subject(reader: impl BufRead) -> io::Result<String> {
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() && !line.starts_with('#') {
+            return Ok(line);
+        }
+    }
+    Err(error("no lines found"))
+}
+
+/// Lowercase `subject`, joining words with underscores and dropping anything
+/// that isn't alphanumeric. Empty if nothing usable is left.
+///
+/// Since `.` never survives, the result can't contain `..` or end in `.lock`,
+/// both of which git rejects in ref names.
+fn sanitize_branch_name(subject: &str) -> String {
+    let subject_terms: Vec<String> = subject.split_whitespace()
+        .map(|term| term.chars().filter(|&c| c == '_' || c.is_alphanumeric()).collect())
+        .filter(|term: &String| !term.is_empty())
+        .collect();
+    subject_terms.join("_").trim_matches('_').to_lowercase()
+}
+
+/// Shorten a slug to at most `max` characters, preferring to cut between
+/// words and never leaving a trailing underscore.
+fn truncate_slug(slug: &str, max: usize) -> String {
+    if slug.chars().count() <= max {
+        return slug.to_string();
+    }
+    let end = slug.char_indices().nth(max).map_or(slug.len(), |(i, _)| i);
+    let cut = &slug[..end];
+    // Cutting right before an underscore already falls between words.
+    let cut = match cut.rfind('_') {
+        Some(i) if i > 0 && !slug[end..].starts_with('_') => &cut[..i],
+        _ => cut,
+    };
+    cut.trim_end_matches('_').to_string()
+}
+
+fn ref_exists(full_name: &str) -> io::Result<bool> {
+    git(["show-ref", "--verify", "--quiet", full_name])
+        .status()
+        .map(|s| s.success())
+}
+
+fn branch_exists(name: &str) -> io::Result<bool> {
+    ref_exists(&format!("refs/heads/{name}"))
+}
+
+fn tag_exists(name: &str) -> io::Result<bool> {
+    ref_exists(&format!("refs/tags/{name}"))
+}
+
+/// `base`, or `base` with the first numeric suffix for which `exists` is
+/// false.
+fn unique_name(base: &str, exists: fn(&str) -> io::Result<bool>) -> io::Result<String> {
+    let mut name = base.to_string();
+    let mut suffix = 2;
+    while exists(&name)? {
+        name = format!("{base}-{suffix}");
+        suffix += 1;
+    }
+    Ok(name)
+}
+
+/// Start of the name of the branch a stash is committed on before it gets
+/// its real name. Older versions used exactly this name.
+const TEMP_BRANCH_BASE: &str = "stash/__TEMP_STASH__";
+
+/// The temporary branch for this process. The pid keeps it from colliding
+/// with one left behind by a run that crashed partway through.
+fn temp_branch() -> &'static str {
+    static NAME: OnceLock<String> = OnceLock::new();
+    NAME.get_or_init(|| format!("{TEMP_BRANCH_BASE}-{}", std::process::id()))
+}
+
+/// Whether a process with this pid is still running. Off Unix this can't be
+/// told, so it's assumed not to be.
+fn process_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    unsafe {
+        // Signal 0 only checks that the process exists and may be signaled.
+        sys::kill(pid as i32, 0) == 0
+            || io::Error::last_os_error().raw_os_error() == Some(sys::EPERM)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        false
+    }
+}
+
+/// Temporary branches left behind by earlier runs that didn't finish.
+fn stale_temp_branches() -> io::Result<Vec<String>> {
+    let pattern = format!("refs/heads/{TEMP_BRANCH_BASE}*");
+    let output = git(["for-each-ref", "--format=%(refname:short)", &pattern]).output()?;
+    let stale = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|branch| match branch.strip_prefix(TEMP_BRANCH_BASE) {
+            Some("") => true,
+            Some(suffix) => match suffix.strip_prefix('-').and_then(|pid| pid.parse().ok()) {
+                Some(pid) => pid != std::process::id() && !process_alive(pid),
+                None => false,
+            },
+            None => false,
+        })
+        .map(str::to_string)
+        .collect();
+    Ok(stale)
+}
+
+/// Offer to delete temporary branches left by interrupted runs. Batch and
+/// quiet runs never prompt; batch runs just point the branches out.
+fn clean_stale_temp_branches(args: &Args) -> io::Result<()> {
+    if args.quiet {
+        return Ok(());
+    }
+    for branch in stale_temp_branches()? {
+        let output = git(["log", "-1", "--format=%s", &branch]).output()?;
+        let subject = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let Palette { clear, bold, yellow, .. } = palette(Stream::Stderr);
+        eprintln!(
+            "{bold}{yellow}{}{clear}",
+            Message::StaleTempBranch { branch: &branch, subject: &subject },
+        );
+        if args.batch.is_some() {
+            continue;
+        }
+        print!("{} ", Message::DeleteStaleTempBranch);
+        io::stdout().flush()?;
+        match read_key(args) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!();
+                return Ok(());
+            }
+            result => {
+                if is_yes(&result?) {
+                    git_mut(["branch", "-D", &branch]).status()?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A temporary linked worktree, removed again when dropped.
+struct TempWorktree {
+    path: PathBuf,
+}
+
+impl TempWorktree {
+    /// Check out a new `branch` at HEAD in a fresh temporary directory.
+    fn create(branch: &str) -> io::Result<Self> {
+        let path = std::env::temp_dir()
+            .join(format!("git-stash-inbox-{}", std::process::id()));
+        let status = git_mut(["worktree", "add", "-q", "-b", branch])
+            .arg(&path)
+            .arg("HEAD")
+            .status()?;
+        if !status.success() {
+            return Err(error("failed to create a temporary worktree"));
+        }
+        Ok(TempWorktree { path })
+    }
+}
+
+impl Drop for TempWorktree {
+    fn drop(&mut self) {
+        let _ = git_mut(["worktree", "remove", "--force"]).arg(&self.path).status();
+    }
+}
+
+/// Where the last commit message was written for the worktree at `dir`.
+fn commit_message_path(dir: Option<&Path>) -> io::Result<PathBuf> {
+    let mut rev_parse = git(["rev-parse", "--git-path", "COMMIT_EDITMSG"]);
+    if let Some(dir) = dir {
+        rev_parse.current_dir(dir);
+    }
+    let output = rev_parse.output()?;
+    if !output.status.success() {
+        return Err(error("could not locate COMMIT_EDITMSG"));
+    }
+    let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(match dir {
+        Some(dir) => dir.join(path),
+        None => path,
+    })
+}
+
+/// How to make the commit that rescues a stash.
+struct CommitSpec<'a> {
+    message: Option<&'a str>,
+    /// Author date to record, instead of the current time.
+    date: Option<&'a str>,
+    /// GPG key to sign with, empty for the default key.
+    sign: Option<&'a str>,
+    /// Author name and email to record, instead of the current user's.
+    author: Option<(&'a str, &'a str)>,
+    /// Run the pre-commit and commit-msg hooks.
+    verify: bool,
+}
+
+impl<'a> CommitSpec<'a> {
+    fn new(stash: &'a Stash, message: Option<&'a str>, args: &'a Args) -> Self {
+        CommitSpec {
+            message,
+            date: (!args.current_date).then_some(stash.date.as_str()),
+            sign: args.sign.as_deref(),
+            author: args.stash_author.then_some((stash.author.as_str(), stash.email.as_str())),
+            verify: args.verify.unwrap_or(false),
+        }
+    }
+}
+
+fn commit_command(spec: &CommitSpec) -> Git {
+    // Without a message, `git commit` opens the editor.
+    let mut commit = git_mut(["commit"]);
+    commit.foreground();
+    // Hooks are skipped by default, both for speed and because a rescued
+    // stash is often work in progress that wouldn't pass them.
+    if !spec.verify {
+        commit.arg("-n");
+    }
+    if let Some(message) = spec.message {
+        commit.args(["-m", message]);
+    }
+    // Without `-S`, git still signs if `commit.gpgsign` is set.
+    if let Some(key) = spec.sign {
+        commit.arg(format!("-S{key}"));
+    }
+    if let Some(date) = spec.date {
+        commit.env("GIT_AUTHOR_DATE", date);
+    }
+    if let Some((name, email)) = spec.author {
+        commit.env("GIT_AUTHOR_NAME", name).env("GIT_AUTHOR_EMAIL", email);
+    }
+    commit
+}
+
+/// Fail with a clear message if git has no identity to commit as, before
+/// anything is checked out or applied.
+fn check_committer_identity() -> io::Result<()> {
+    let known = git(["var", "GIT_COMMITTER_IDENT"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?
+        .success();
+    if !known {
+        return Err(error(
+            "git doesn't know who you are, so stashes can't be committed; \
+            set `git config user.name` and `git config user.email` first"
+        ));
+    }
+    Ok(())
+}
+
+/// Where `with_autostash` keeps local changes while they're stashed away.
+const AUTOSTASH_REF: &str = "refs/stash-inbox/autostash";
+
+/// Run `f` with any local changes stashed away, putting them back exactly,
+/// staged state included, afterwards, even if `f` fails. The autostash is
+/// kept under `AUTOSTASH_REF` instead of in the stash list, so the stashes
+/// being triaged keep their indices and can't be mistaken for it.
+fn with_autostash<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    if !has_local_changes()? {
+        return f();
+    }
+    if DRY_RUN.load(Ordering::Relaxed) {
+        println!("{}", Message::WouldAutostash);
+        return f();
+    }
+    let message = "git-stash-inbox autostash";
+    if !git_mut(["stash", "push", "-q", "-u", "-m", message]).status()?.success() {
+        return Err(error("failed to autostash local changes"));
+    }
+    let output = git(["rev-parse", "--verify", "stash@{0}"]).output()?;
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let saved = output.status.success()
+        && git_mut(["update-ref", "-m", message, AUTOSTASH_REF, &hash]).status()?.success();
+    if !saved {
+        return Err(error(
+            "failed to record the autostash; your local changes are in stash@{0}"
+        ));
+    }
+    git_mut(["stash", "drop", "-q", "stash@{0}"]).status()?;
+
+    let result = f();
+    // `--index` refuses to apply over staged changes, such as those `f` may
+    // have left, so then the local changes can only come back unstaged.
+    let mut restore = git_mut(["stash", "apply", "-q"]);
+    if git(["diff", "--cached", "--quiet"]).status()?.success() {
+        restore.arg("--index");
+    } else {
+        let Palette { clear, bold, yellow, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{yellow}{}{clear}", Message::AutostashUnstaged);
+    }
+    if !restore.arg(&hash).status()?.success() {
+        return Err(error(&format!(
+            "couldn't restore your local changes; they are saved in {AUTOSTASH_REF}, \
+            run `git stash apply --index {hash}` to get them back"
+        )));
+    }
+    git_mut(["update-ref", "-d", AUTOSTASH_REF]).status()?;
+    result
+}
+
+/// Apply a stash onto a clean checkout and commit everything, running each
+/// git command through `checkout` to pick the checkout. Returns `false`, with
+/// the checkout reset, if the stash doesn't apply cleanly or the commit fails.
+fn apply_and_commit(
+    stash_name: &str,
+    spec: &CommitSpec,
+    checkout: impl Fn(Git) -> Git,
+) -> io::Result<bool> {
+    // `:/` covers the whole tree even when run from a subdirectory, so that
+    // files restored from a stash's untracked parent, wherever they are, get
+    // committed or else cleaned up.
+    let committed = checkout(git_mut(["stash", "apply", stash_name])).status()?.success()
+        && checkout(git_mut(["add", "-A", "--", ":/"])).status()?.success()
+        && checkout(commit_command(spec)).status()?.success();
+    if !committed {
+        checkout(git_mut(["reset", "-q", "--hard"])).status()?;
+        checkout(git_mut(["clean", "-f", "-d", "--", ":/"])).status()?;
+    }
+    Ok(committed)
+}
+
+/// A new branch checked out for committing stashes onto, either in the
+/// current checkout or in a temporary worktree so that the current checkout,
+/// and any local changes in it, are never touched.
+struct RescueCheckout {
+    branch: String,
+    /// Where to return to afterwards when checked out in place.
+    head: Option<Head>,
+    worktree: Option<TempWorktree>,
+}
+
+impl RescueCheckout {
+    /// Create `branch` at HEAD and check it out.
+    fn create(branch: &str, in_worktree: bool) -> io::Result<Self> {
+        if in_worktree {
+            return Ok(RescueCheckout {
+                branch: branch.to_string(),
+                head: None,
+                worktree: Some(TempWorktree::create(branch)?),
+            });
+        }
+        let head = Head::current()?;
+        if let Head::Detached(hash) = &head {
+            let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+            eprintln!("{bold}{red}{}{clear}", Message::DetachedHead { hash });
+        }
+        if !git_mut(["checkout", "-b", branch]).status()?.success() {
+            return Err(error(&format!("failed to create branch {branch}")));
+        }
+        Ok(RescueCheckout { branch: branch.to_string(), head: Some(head), worktree: None })
+    }
+
+    /// Run `git` in this checkout.
+    fn git(&self, mut git: Git) -> Git {
+        if let Some(worktree) = &self.worktree {
+            git.current_dir(&worktree.path);
+        }
+        git
+    }
+
+    /// Apply a stash and commit it onto the branch; see `apply_and_commit`.
+    fn commit_stash(&self, stash_name: &str, spec: &CommitSpec) -> io::Result<bool> {
+        apply_and_commit(stash_name, spec, |git| self.git(git))
+    }
+
+    /// Copy the last commit message somewhere that outlives the checkout.
+    fn save_commit_message(&self) -> io::Result<PathBuf> {
+        let kept = std::env::temp_dir()
+            .join(format!("git-stash-inbox-{}-COMMIT_EDITMSG", std::process::id()));
+        if !DRY_RUN.load(Ordering::Relaxed) {
+            let dir = self.worktree.as_ref().map(|w| w.path.as_path());
+            std::fs::copy(commit_message_path(dir)?, &kept)?;
+        }
+        Ok(kept)
+    }
+
+    /// Return to the original HEAD, deleting the branch unless `keep` is set.
+    fn finish(self, keep: bool) -> io::Result<()> {
+        if let Some(head) = &self.head {
+            head.restore()?;
+        }
+        drop(self.worktree);
+        if !keep {
+            git_mut(["branch", "-D", &self.branch]).status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Commit a stash onto `temp_branch()`, leaving the stash in place. The
+/// message is prompted for in an editor unless one is given, and the commit
+/// is dated when the stash was made unless `--current-date` is passed. With
+/// `--worktree` this happens in a temporary worktree, so local changes don't
+/// get in the way.
+///
+/// Returns the commit's subject, or `None` if nothing was committed.
+fn commit_to_temp_branch(
+    stash: &Stash,
+    can_save_branch: bool,
+    message: Option<&str>,
+    args: &Args,
+) -> io::Result<Option<String>> {
+    let stash_name = stash_ref(stash.index);
+    if !can_save_branch && !args.worktree {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::CommitWithLocalChanges);
+        record_failure();
+        return Ok(None);
+    }
+    if !stash_changes_head(stash.index)? {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::NothingToCommit);
+        record_failure();
+        return Ok(None);
+    }
+
+    check_committer_identity()?;
+    let spec = CommitSpec::new(stash, message, args);
+    let commit = || {
+        let checkout = RescueCheckout::create(temp_branch(), args.worktree)?;
+        if !checkout.commit_stash(&stash_name, &spec)? {
+            checkout.finish(false)?;
+            return Ok(None);
+        }
+        let commit_msg_file = checkout.save_commit_message()?;
+        checkout.finish(true)?;
+        Ok(Some(commit_msg_file))
+    };
+    let committed = if args.autostash && !args.worktree {
+        with_autostash(commit)?
+    } else {
+        commit()?
+    };
+    let Some(commit_msg_file) = committed else {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::CommitFailed { stash: &stash_name });
+        record_failure();
+        return Ok(None);
+    };
+
+    let subject = match message {
+        Some(message) => message.to_string(),
+        // No editor was opened, so there's no message to name anything after.
+        None if DRY_RUN.load(Ordering::Relaxed) => "<commit message>".to_string(),
+        None => first_commit_subject(BufReader::new(File::open(&commit_msg_file)?))?,
+    };
+    let _ = std::fs::remove_file(&commit_msg_file);
+    Ok(Some(subject))
+}
+
+/// The subject of the `number`th of `count` rescue commits in a batch: the
+/// stash's own, after `[number/count]` with `--number-commits`.
+fn batch_subject(stash: &Stash, number: usize, count: usize, args: &Args) -> String {
+    if args.number_commits {
+        format!("[{number}/{count}] {}", stash.subject())
+    } else {
+        stash.subject().to_string()
+    }
+}
+
+/// The subject without the prefix from `batch_subject`, if it has one.
+fn unnumbered_subject(subject: &str) -> &str {
+    let Some((number, rest)) = subject.strip_prefix('[').and_then(|s| s.split_once("] ")) else {
+        return subject;
+    };
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match number.split_once('/') {
+        Some((n, count)) if is_number(n) && is_number(count) => rest,
+        _ => subject,
+    }
+}
+
+/// The part of a rescue branch or tag name that comes from the commit
+/// subject, falling back to the stash's short hash.
+fn rescue_slug(subject: &str, stash: &Stash, args: &Args) -> io::Result<String> {
+    let slug = truncate_slug(&sanitize_branch_name(subject), args.max_branch_length());
+    if !slug.is_empty() {
+        return Ok(slug);
+    }
+    let output = git(["rev-parse", "--short", &stash.hash]).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit a stash to a new branch named after the commit message, leaving
+/// the stash in place; see `commit_to_temp_branch`.
+///
+/// Returns the name of the new branch, or `None` if nothing was committed.
+fn branch_stash(
+    stash: &Stash,
+    can_save_branch: bool,
+    message: Option<&str>,
+    args: &Args,
+) -> io::Result<Option<String>> {
+    let Some(subject) = commit_to_temp_branch(stash, can_save_branch, message, args)? else {
+        return Ok(None);
+    };
+    // The numbers order the history, but would only clutter branch names.
+    let subject = if args.number_commits { unnumbered_subject(&subject) } else { &subject };
+    let slug = rescue_slug(subject, stash, args)?;
+    let mut new_branch_name =
+        unique_name(&format!("{}{slug}", args.branch_prefix()), branch_exists)?;
+    if args.edit_branch_name {
+        new_branch_name = prompt_branch_name(&new_branch_name)?;
+    }
+    git_mut(["branch", "-m", temp_branch(), &new_branch_name]).status()?;
+    Ok(Some(new_branch_name))
+}
+
+/// Offer `proposed` as a rescue branch's name and ask until the answer is a
+/// branch name git accepts that isn't taken. The commit is already made and
+/// only waiting for a name, so an empty answer, or none at all, keeps
+/// `proposed`.
+fn prompt_branch_name(proposed: &str) -> io::Result<String> {
+    loop {
+        print!("{} ", Message::BranchName { proposed });
+        io::stdout().flush()?;
+        let answer = match read_line() {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!();
+                String::new()
+            }
+            result => result?,
+        };
+        let branch = answer.trim();
+        if branch.is_empty() || branch == proposed {
+            return Ok(proposed.to_string());
+        }
+        // `--branch` also expands shorthands like `@{-1}`, which aren't names.
+        let check = git(["check-ref-format", "--branch", branch]).output()?;
+        let valid = check.status.success()
+            && String::from_utf8_lossy(&check.stdout).trim() == branch;
+        if !valid {
+            println!("{}", Message::InvalidBranchName { branch });
+        } else if branch_exists(branch)? {
+            println!("{}", Message::BranchExists { branch });
+        } else {
+            return Ok(branch.to_string());
+        }
+    }
+}
+
+const TAG_PREFIX: &str = "stash/";
+
+/// Commit a stash and point a new annotated tag at the commit instead of a
+/// branch, then drop the stash. Returns the name of the tag, or `None` if
+/// nothing was committed.
+fn tag_stash(stash: &Stash, can_save_branch: bool, args: &Args) -> io::Result<Option<String>> {
+    let Some(subject) = commit_to_temp_branch(stash, can_save_branch, None, args)? else {
+        return Ok(None);
+    };
+    let slug = rescue_slug(&subject, stash, args)?;
+    let tag = unique_name(&format!("{TAG_PREFIX}{slug}"), tag_exists)?;
+    let tagged = git_mut(["tag", "-a", &tag, "-m", &subject, temp_branch()]).status()?.success();
+    if !tagged {
+        // The temporary branch is all that holds on to the commit now.
+        return Err(error(&format!(
+            "failed to create tag {tag}; the commit is on {}", temp_branch()
+        )));
+    }
+    git_mut(["branch", "-D", temp_branch()]).status()?;
+    if !git_mut(["stash", "drop", &stash_ref(stash.index)]).status()?.success() {
+        record_failure();
+    }
+    Ok(Some(tag))
+}
+
+/// Commit a stash to a new `stash/` branch named after `message`, then drop
+/// it. The working tree must be clean. Returns the new branch's name, or
+/// `None` if nothing was committed and the stash was kept.
+pub fn rescue_to_branch(stash: &Stash, message: &str) -> io::Result<Option<String>> {
+    commit_to_branch(stash, !has_local_changes()?, Some(message), &Args::new())
+}
+
+/// Like `branch_stash`, but drops the stash once it's safely on a branch.
+fn commit_to_branch(
+    stash: &Stash,
+    can_save_branch: bool,
+    message: Option<&str>,
+    args: &Args,
+) -> io::Result<Option<String>> {
+    let branch = branch_stash(stash, can_save_branch, message, args)?;
+    if branch.is_some() {
+        let stash_name = stash_ref(stash.index);
+        if !git_mut(["stash", "drop", &stash_name]).status()?.success() {
+            record_failure();
+        }
+    }
+    Ok(branch)
+}
+
+/// Commit a stash straight onto the current branch, with the stash's message,
+/// and drop it, for stashes that belong where they are rather than on a
+/// branch of their own. Refuses with local changes, which would end up in the
+/// commit. Returns whether the stash was committed.
+fn commit_here(stash: &Stash, args: &Args) -> io::Result<bool> {
+    let stash_name = stash_ref(stash.index);
+    let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+    if has_local_changes()? {
+        eprintln!("{bold}{red}{}{clear}", Message::CommitHereWithLocalChanges);
+        record_failure();
+        return Ok(false);
+    }
+    if !stash_changes_head(stash.index)? {
+        eprintln!("{bold}{red}{}{clear}", Message::NothingToCommit);
+        record_failure();
+        return Ok(false);
+    }
+    check_committer_identity()?;
+    let spec = CommitSpec::new(stash, Some(stash.subject()), args);
+    if !apply_and_commit(&stash_name, &spec, |git| git)? {
+        eprintln!("{bold}{red}{}{clear}", Message::CommitFailed { stash: &stash_name });
+        record_failure();
+        return Ok(false);
+    }
+    if !git_mut(["stash", "drop", &stash_name]).status()?.success() {
+        record_failure();
+    }
+    Ok(true)
+}
+
+/// Counts of the actions taken during a triage session.
+#[derive(Default)]
+struct Summary {
+    dropped: u32,
+    branched: u32,
+    tagged: u32,
+    committed: u32,
+    applied: u32,
+    popped: u32,
+    skipped: u32,
+}
+
+impl Summary {
+    fn print(&self) {
+        eprintln!("{}", Message::Summary { summary: self });
+    }
+}
+
+/// Write the stash's patch to `path`.
+fn export_stash(stash_num: u32, path: &Path) -> io::Result<()> {
+    File::create(path)?.write_all(&stash_patch(stash_num)?)
+}
+
+/// The stash's full patch, binary changes included, as `git apply` takes it.
+fn stash_patch(stash_num: u32) -> io::Result<Vec<u8>> {
+    let mut patch = stash_diff(&[]);
+    patch.args(["-p", "--binary", "--color=never"]);
+    stash_diff_target(&mut patch, stash_num, &[]);
+    let output = patch.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(error(&format!("failed to show {}", stash_ref(stash_num))));
+    }
+    Ok(output.stdout)
+}
+
+/// The clipboard tools to look for, in order, each with the arguments that
+/// make it set the clipboard from stdin.
+fn clipboard_tools() -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(windows) {
+        return vec![("clip", &[])];
+    }
+    if cfg!(target_os = "macos") {
+        return vec![("pbcopy", &[])];
+    }
+    let mut tools: Vec<(&str, &[&str])> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &[]));
+    }
+    tools.push(("xclip", &["-selection", "clipboard"]));
+    tools.push(("xsel", &["--clipboard", "--input"]));
+    // Under WSL, the Windows clipboard is the one that matters.
+    tools.push(("clip.exe", &[]));
+    tools
+}
+
+/// Copy the stash's patch to the system clipboard with the first clipboard
+/// tool that's installed.
+fn copy_to_clipboard(stash_num: u32) -> io::Result<()> {
+    let patch = stash_patch(stash_num)?;
+    let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+    let tools = clipboard_tools();
+    for &(tool, tool_args) in &tools {
+        // Some tools stay behind to serve the clipboard, so they mustn't hold
+        // on to our output.
+        let spawned = Command::new(tool)
+            .args(tool_args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match spawned {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            result => result?,
+        };
+        child.stdin.take()
+            .ok_or_else(|| error("clipboard tool has no stdin"))?
+            .write_all(&patch)?;
+        if !child.wait()?.success() {
+            eprintln!("{bold}{red}{}{clear}", Message::ClipboardFailed { tool });
+            record_failure();
+            return Ok(());
+        }
+        println!("{}", Message::Copied { stash: &stash_ref(stash_num) });
+        return Ok(());
+    }
+    let names: Vec<&str> = tools.iter().map(|&(tool, _)| tool).collect();
+    eprintln!("{bold}{red}{}{clear}", Message::NoClipboard { tools: &names.join(", ") });
+    record_failure();
+    Ok(())
+}
+
+/// Show the stash's patch in the editor git would use, from a temporary file
+/// that is deleted afterwards, so any edits are thrown away.
+fn open_in_editor(stash_num: u32) -> io::Result<()> {
+    let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+    let output = git(["var", "GIT_EDITOR"]).output()?;
+    let editor = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || editor.is_empty() {
+        eprintln!("{bold}{red}{}{clear}", Message::NoEditor);
+        record_failure();
+        return Ok(());
+    }
+    let path = std::env::temp_dir().join(format!(
+        "git-stash-inbox-{}-stash-{stash_num}.patch",
+        std::process::id(),
+    ));
+    if let Err(e) = export_stash(stash_num, &path) {
+        let path = path.to_string_lossy();
+        eprintln!("{bold}{red}{}{clear}", Message::PatchNotWritten { path: &path, error: &e });
+        record_failure();
+        return Ok(());
+    }
+    // Like git, let the shell split the editor command.
+    let mut open = Command::new("sh");
+    open.args(["-c", &format!("{editor} \"$@\""), &editor]).arg(&path);
+    default_interrupts(&mut open);
+    let status = {
+        let _ignoring = IgnoreInterrupts::new();
+        open.status()
+    };
+    let _ = std::fs::remove_file(&path);
+    if !status?.success() {
+        eprintln!("{bold}{red}{}{clear}", Message::EditorFailed);
+        record_failure();
+    }
+    Ok(())
+}
+
+/// Ask where to export the stash, confirming before overwriting a file.
+fn prompt_export(stash: &Stash, args: &Args) -> io::Result<()> {
+    let slug = sanitize_branch_name(stash.subject());
+    let default = if slug.is_empty() {
+        format!("stash-{}.patch", stash.index)
+    } else {
+        format!("{slug}.patch")
+    };
+    print!("{} ", Message::ExportTo { default: &default });
+    io::stdout().flush()?;
+    let answer = read_line()?;
+    let path = PathBuf::from(match answer.trim() {
+        "" => &default,
+        path => path,
+    });
+    if path.exists() {
+        print!("{} ", Message::Overwrite { path: &path.to_string_lossy() });
+        io::stdout().flush()?;
+        if !is_yes(&read_key(args)?) {
+            return Ok(());
+        }
+    }
+    export_stash(stash.index, &path)?;
+    println!("{}", Message::Wrote { path: &path.to_string_lossy() });
+    Ok(())
+}
+
+/// List which of `stashes` are already applied and drop them all after one
+/// confirmation. Returns the stashes that were dropped.
+fn prompt_drop_applied<'a>(
+    stashes: &'a [Stash],
+    args: &Args,
+    cache: &mut StashCache,
+) -> io::Result<Vec<&'a Stash>> {
+    let mut applied = Vec::new();
+    for stash in stashes {
+        if cache.is_applied(stash)? {
+            applied.push(stash);
+        }
+    }
+    if applied.is_empty() {
+        println!("{}", Message::NoneApplied);
+        return Ok(applied);
+    }
+    for stash in &applied {
+        println!("  {}: {}", stash_ref(stash.index), stash.message);
+    }
+    print!("{} ", Message::DropAppliedConfirm { count: applied.len() });
+    io::stdout().flush()?;
+    if !is_yes(&read_key(args)?) {
+        return Ok(Vec::new());
+    }
+    let mut dropped = Vec::new();
+    // Highest index first, so each drop leaves the rest where they were.
+    for stash in applied.into_iter().rev() {
+        if git_mut(["stash", "drop", "-q", &stash_ref(stash.index)]).status()?.success() {
+            dropped.push(stash);
+        } else {
+            record_failure();
+        }
+    }
+    Ok(dropped)
+}
+
+/// Quote a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Print every stash, as plain lines or as a JSON array of objects.
+fn list(args: &Args) -> io::Result<()> {
+    let stashes = selected_stashes(args)?;
+    if args.format == ListFormat::Text {
+        for stash in &stashes {
+            println!("{}: {}", stash_ref(stash.index), stash.message);
+        }
+        return Ok(());
+    }
+    let mut objects = Vec::with_capacity(stashes.len());
+    for stash in &stashes {
+        let files = git_stash_name_status(stash.index, &[])?.len();
+        objects.push(format!(
+            "{{\"index\":{},\"message\":{},\"branch\":{},\"date\":{},\"files\":{files}}}",
+            stash.index,
+            json_string(&stash.message),
+            json_string(&stash.branch),
+            json_string(&stash.date),
+        ));
+    }
+    println!("[{}]", objects.join(","));
+    Ok(())
+}
+
+/// Print a numbered line for each stash about to be triaged, pointing at
+/// the one at `start` where the session begins, if given, and with a
+/// checkbox for each of `marks`, if given.
+fn print_overview(
+    stashes: &[Stash],
+    start: Option<usize>,
+    marks: Option<&[bool]>,
+    paths: &[String],
+) -> io::Result<()> {
+    let Palette { clear, bold, yellow, .. } = palette(Stream::Stdout);
+    let width = stashes.len().to_string().len();
+    for (i, stash) in stashes.iter().enumerate() {
+        let pointer = if start == Some(i) { ">" } else { " " };
+        let checkbox = match marks {
+            Some(marks) if marks[i] => "[x] ",
+            Some(_) => "[ ] ",
+            None => "",
+        };
+        let files = Message::FileCount { count: git_stash_name_status(stash.index, paths)?.len() };
+        println!(
+            "{pointer}{checkbox}{:>width$}. {bold}{yellow}{}{clear} {files}, {}: {}",
+            i + 1, stash_ref(stash.index), stash.age, stash.subject(),
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// Apply a patch to the (clean) working tree and stash the result.
+fn import_patch(path: &Path) -> io::Result<()> {
+    if has_local_changes()? {
+        return Err(error("can't import a patch with local changes; commit or stash them first"));
+    }
+    // `git apply` is atomic, so a failure here leaves the tree untouched.
+    let path_arg = path.as_os_str();
+    if !git_mut([OsStr::new("apply"), OsStr::new("--index"), path_arg]).status()?.success() {
+        return Err(error(&format!("failed to apply {}", path.display())));
+    }
+    let message = format!("Imported from {}", path.display());
+    if !git_mut(["stash", "push", "-q", "-m", &message]).status()?.success() {
+        // The tree was clean before applying, so this discards only the patch.
+        git_mut(["reset", "-q", "--hard", "HEAD"]).status()?;
+        return Err(error("failed to stash the imported patch"));
+    }
+    println!("{}", Message::Imported { path: &path.to_string_lossy(), stash: &stash_ref(0) });
+    Ok(())
+}
+
+/// Commit every stash, oldest first, onto a single new `branch`, dropping
+/// each one that commits cleanly. Stashes that don't apply are left alone.
+fn collect_stashes(branch: &str, args: &Args) -> io::Result<()> {
+    let valid = git(["check-ref-format", "--branch", branch]).output()?.status.success();
+    if !valid {
+        return Err(error(&format!("invalid branch name '{branch}'")));
+    }
+    if branch_exists(branch)? {
+        return Err(error(&format!("branch {branch} already exists")));
+    }
+    check_committer_identity()?;
+    let stashes = selected_stashes(args)?;
+    let collect = || {
+        let checkout = RescueCheckout::create(branch, args.worktree)?;
+        let mut failed = Vec::new();
+        // As in `run_batch`, oldest first keeps the remaining indices valid.
+        for (i, stash) in stashes.iter().rev().enumerate() {
+            let stash_name = stash_ref(stash.index);
+            let subject = batch_subject(stash, i + 1, stashes.len(), args);
+            let spec = CommitSpec::new(stash, Some(&subject), args);
+            if !checkout.commit_stash(&stash_name, &spec)? {
+                failed.push(stash);
+                continue;
+            }
+            if !git_mut(["stash", "drop", "-q", &stash_name]).status()?.success() {
+                record_failure();
+            }
+            if !args.quiet {
+                let message = &stash.message;
+                println!("{}", Message::Committed { stash: &stash_name, branch, message });
+            }
+            print_event(args, "BRANCHED", stash, Some(branch));
+        }
+        checkout.finish(failed.len() < stashes.len())?;
+        Ok(failed)
+    };
+    let failed = if args.autostash && !args.worktree {
+        with_autostash(collect)?
+    } else {
+        collect()?
+    };
+    let collected = stashes.len() - failed.len();
+    if !args.quiet {
+        let total = stashes.len();
+        println!("{}", Message::Collected { count: collected, total, branch });
+    }
+    if !failed.is_empty() {
+        record_failure();
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::NotCollected);
+        for stash in failed {
+            eprintln!("  {}", stash.message);
+        }
+    }
+    Ok(())
+}
+
+/// Apply every stash, oldest first so that each lands on top of the ones made
+/// before it, stopping at the first that doesn't apply cleanly.
+fn apply_all(args: &Args) -> io::Result<()> {
+    if !args.autostash && has_local_changes()? {
+        return Err(error("can't apply stashes with local changes; commit them or use --autostash"));
+    }
+    let stashes = selected_stashes(args)?;
+    let apply = || {
+        let mut count = 0;
+        for stash in stashes.iter().rev() {
+            let stash_name = stash_ref(stash.index);
+            if !unstash("apply", &stash_name, args.index)? {
+                record_failure();
+                let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+                eprintln!(
+                    "{bold}{red}{}{clear}",
+                    Message::ApplyAllStopped { stash: &stash_name, message: &stash.message },
+                );
+                for path in conflicted_paths()? {
+                    eprintln!("    {path}");
+                }
+                break;
+            }
+            if !args.quiet {
+                println!("{}", Message::Applied { stash: &stash_name, message: &stash.message });
+            }
+            print_event(args, "APPLIED", stash, None);
+            count += 1;
+        }
+        Ok(count)
+    };
+    let count = if args.autostash { with_autostash(apply)? } else { apply()? };
+    if !args.quiet {
+        println!("{}", Message::AppliedCount { count, total: stashes.len() });
+    }
+    Ok(())
+}
+
+fn run_batch(batch: Batch, args: &Args, can_save_branch: bool) -> io::Result<()> {
+    if batch == Batch::ApplyAll {
+        return apply_all(args);
+    }
+    if batch != Batch::DropApplied && !can_save_branch && !args.worktree {
+        return Err(error("can't commit stashes to branches with local changes"));
+    }
+    if let (Batch::Collect, Some(branch)) = (batch, &args.collect_branch) {
+        return collect_stashes(branch, args);
+    }
+    let stashes = selected_stashes(args)?;
+    let mut count = 0;
+    // Work from the oldest stash up so that each drop leaves the indices of
+    // the stashes still to be processed untouched.
+    for (i, stash) in stashes.iter().rev().enumerate() {
+        let stash_name = stash_ref(stash.index);
+        match batch {
+            Batch::DropApplied => {
+                if !stash_is_applied(stash)? {
+                    continue;
+                }
+                if !git_mut(["stash", "drop", "-q", &stash_name]).status()?.success() {
+                    record_failure();
+                    continue;
+                }
+                println!("{}", Message::Dropped { stash: &stash_name, message: &stash.message });
+                print_event(args, "DROPPED", stash, None);
+            }
+            Batch::Collect => unreachable!("handled by collect_stashes"),
+            Batch::ApplyAll => unreachable!("handled by apply_all"),
+            Batch::BranchAll => {
+                let subject = batch_subject(stash, i + 1, stashes.len(), args);
+                let Some(branch) = commit_to_branch(
+                    stash, can_save_branch, Some(&subject), args
+                )? else {
+                    continue;
+                };
+                let message = &stash.message;
+                println!("{}", Message::Committed { stash: &stash_name, branch: &branch, message });
+                print_event(args, "BRANCHED", stash, Some(&branch));
+            }
+        }
+        count += 1;
+    }
+    let total = stashes.len();
+    let done = match batch {
+        Batch::DropApplied => Message::DroppedCount { count, total },
+        Batch::BranchAll | Batch::Collect => Message::BranchedCount { count, total },
+        Batch::ApplyAll => unreachable!("handled by apply_all"),
+    };
+    if !args.quiet {
+        println!("{done}");
+    }
+    Ok(())
+}
+
+/// Print a stash's header and its diff, stat or file names as chosen by
+/// `args`.
+fn show_stash(stash: &Stash, args: &Args, cache: &mut StashCache) -> io::Result<()> {
+    let stash_num = stash.index;
+    if !args.quiet {
+        stash.print_header(&HeaderNotes {
+            applied: cache.is_applied(stash)?,
+            old: args.mark_older_than.is_some_and(|secs| stash.older_than(secs)),
+            // `--stat` already ends with the same line.
+            shortstat: if args.shortstat && !args.stat {
+                Some(cache.shortstat(stash)?.to_string())
+            } else {
+                None
+            },
+        });
+    }
+    if !args.stat && !args.names {
+        return git_stash_show(stash, args, cache);
+    }
+    if args.stat {
+        git_stash_stat(stash_num, &args.paths)?;
+    }
+    if args.names {
+        print_name_status(&git_stash_name_status(stash_num, &args.paths)?, false);
+    }
+    Ok(())
+}
+
+/// Show a stash with `show_stash`, reporting any failure instead of
+/// returning it, so that a stash git can't read, such as one with a missing
+/// object, can still be skipped or dropped. Returns false only if the stash
+/// no longer exists, dropped by something else since the list was read.
+fn show_or_report(stash: &Stash, args: &Args, cache: &mut StashCache) -> io::Result<bool> {
+    let Err(e) = show_stash(stash, args, cache) else {
+        return Ok(true);
+    };
+    if !list_stashes(None)?.iter().any(|listed| listed.hash == stash.hash) {
+        return Ok(false);
+    }
+    report_error(&e);
+    Ok(true)
+}
+
+/// Print an error the session carries on past, remembering it for the exit
+/// status.
+fn report_error(e: &io::Error) {
+    let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+    eprintln!("{bold}{red}error:{clear} {e}");
+    record_failure();
+}
+
+/// The `--select` mode: step through the stashes marking some, then run one
+/// action on all the marked ones at once, after confirming.
+fn select_stashes(
+    stashes: &[Stash],
+    mut pos: usize,
+    can_save_branch: bool,
+    args: &Args,
+) -> io::Result<()> {
+    let mut marks = vec![false; stashes.len()];
+    let mut cache = StashCache::default();
+    let Palette { clear, bold, blue, .. } = palette(Stream::Stdout);
+    while let Some(stash) = stashes.get(pos) {
+        if !show_or_report(stash, args, &mut cache)? {
+            pos += 1;
+            continue;
+        }
+        let (number, count, marked) = (pos + 1, stashes.len(), marks[pos]);
+        print!("{bold}{blue}{} {clear}", Message::MarkPrompt { number, count, marked });
+        io::stdout().flush()?;
+        let input = match read_key(args) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!();
+                break;
+            }
+            result => result?,
+        };
+        // A line of only spaces is the space key; otherwise, as at the other
+        // prompts, whitespace around the answer doesn't count.
+        let key = match input.trim() {
+            "" if !input.is_empty() => " ",
+            key => key,
+        };
+        match key {
+            " " | "x" => {
+                marks[pos] = !marks[pos];
+                pos += 1;
+            }
+            "" | "s" => pos += 1,
+            "k" => pos = pos.saturating_sub(1),
+            "q" => break,
+            _ => println!("{}", Message::UnknownKey { input: key }),
+        }
+    }
+
+    let count = marks.iter().filter(|&&marked| marked).count();
+    if count == 0 {
+        println!("{}", Message::NoneMarked);
+        return Ok(());
+    }
+    print_overview(stashes, None, Some(&marks), &args.paths)?;
+    let choices = [Action::Drop, Action::Branch, Action::Tag, Action::Apply, Action::Quit];
+    let keys: Vec<String> = choices.iter().map(|&a| args.keymap.key(a).to_string()).collect();
+    let prompt = Message::MarkedAction { count, keys: &keys.join(",") };
+    print!("{bold}{blue}{prompt} {clear}");
+    io::stdout().flush()?;
+    let action = match read_key(args) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            println!();
+            return Ok(());
+        }
+        result => args.keymap.action(result?.trim()),
+    };
+    let (action, event) = match action {
+        Some(action @ Action::Drop) => (action, "DROPPED"),
+        Some(action @ Action::Branch) => (action, "BRANCHED"),
+        Some(action @ Action::Tag) => (action, "TAGGED"),
+        Some(action @ Action::Apply) => (action, "APPLIED"),
+        _ => return Ok(()),
+    };
+    print!("{} ", Message::MarkedConfirm { action, count });
+    io::stdout().flush()?;
+    if !is_yes(&read_key(args)?) {
+        return Ok(());
+    }
+
+    // Oldest first, as in `run_batch`, so each drop leaves the indices of the
+    // stashes still to go untouched, and applies stack up in order.
+    let marked = stashes.iter().zip(&marks).filter(|(_, &marked)| marked).map(|(s, _)| s);
+    let mut done = 0;
+    for stash in marked.rev() {
+        let stash_name = stash_ref(stash.index);
+        // `None` if the action failed, or else the branch or tag it made, if
+        // any.
+        let done_with = match action {
+            Action::Drop => {
+                let dropped = drop_stash(stash)?;
+                if !dropped {
+                    record_failure();
+                }
+                dropped.then_some(None)
+            }
+            Action::Branch => {
+                commit_to_branch(stash, can_save_branch, Some(stash.subject()), args)?.map(Some)
+            }
+            Action::Tag => tag_stash(stash, can_save_branch, args)?.map(Some),
+            _ => {
+                if !apply_stash(stash.index, args)? {
+                    break;
+                }
+                Some(None)
+            }
+        };
+        if let Some(saved_to) = done_with {
+            let (name, message) = (&stash_name, &stash.message);
+            let saved = saved_to.as_deref().unwrap_or_default();
+            let line = match action {
+                Action::Drop => Message::Dropped { stash: name, message },
+                Action::Branch => Message::Committed { stash: name, branch: saved, message },
+                Action::Tag => Message::Tagged { stash: name, tag: saved, message },
+                _ => Message::Applied { stash: name, message },
+            };
+            println!("{line}");
+            print_event(args, event, stash, saved_to.as_deref());
+            done += 1;
+        }
+    }
+    if !args.quiet {
+        println!("{}", Message::MarkedDone { done, count });
+    }
+    Ok(())
+}
+
+/// Run the command line tool on this process's arguments, returning the
+/// status to exit with: 0 on success, 1 if an error stopped the session or
+/// any action failed along the way, and 130 if interrupted by Ctrl-C.
+pub fn cli_main() -> i32 {
+    if let Err(e) = run() {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}error:{clear} {e}");
+        return EXIT_FAILURE;
+    }
+    if FAILED.load(Ordering::Relaxed) {
+        return EXIT_FAILURE;
+    }
+    0
+}
+
+fn run() -> io::Result<()> {
+    install_interrupt_handler();
+    let mut args = parse_args()?;
+    VERBOSE.store(args.verbose, Ordering::Relaxed);
+    TIMEOUT.store(args.timeout.unwrap_or_default(), Ordering::Relaxed);
+    // Completions are set up once per shell, often outside any repository.
+    if let Some(Subcommand::Completions(shell)) = args.subcommand {
+        print!("{}", completions::script(shell));
+        return Ok(());
+    }
+    if let Some(path) = &args.script {
+        load_script(path)?;
+    }
+    check_git_repo()?;
+    args.load_config()?;
+    init_color(args.color, args.color_fallback)?;
+    DRY_RUN.store(args.dry_run, Ordering::Relaxed);
+    if read_only() {
+        let needs_work_tree = match &args.subcommand {
+            Some(Subcommand::Import(_)) => Some("import"),
+            _ if args.batch.is_some() => Some("batch operations"),
+            _ if args.select => Some("--select"),
+            _ if args.tui => Some("--tui"),
+            _ => None,
+        };
+        if let Some(what) = needs_work_tree {
+            return Err(error(&format!(
+                "{what} can't be used in a bare repository, which has no work tree"
+            )));
+        }
+    }
+    match &args.subcommand {
+        Some(Subcommand::Import(path)) => return import_patch(path),
+        Some(Subcommand::List) => return list(&args),
+        Some(Subcommand::Completions(_)) => unreachable!("handled before the repository check"),
+        None => (),
+    }
+    if args.porcelain {
+        open_porcelain()?;
+    }
+    if let Some(path) = &args.log {
+        open_log(path)?;
+    }
+    if read_only() {
+        if !args.quiet {
+            let Palette { clear, bold, yellow, .. } = palette(Stream::Stderr);
+            eprintln!("{bold}{yellow}{}{clear}", Message::ReadOnly);
+        }
+    } else {
+        clean_stale_temp_branches(&args)?;
+    }
+    // `--autostash` gets local changes out of the way as needed.
+    let can_save_branch = !read_only() && (args.autostash || !has_local_changes()?);
+    if let Some(batch) = args.batch {
+        return run_batch(batch, &args, can_save_branch);
+    }
+    // A script can't know to answer, so it gets the error batch modes do.
+    RETRY_LOCKED.store(args.script.is_none(), Ordering::Relaxed);
+    if !can_save_branch && !args.worktree && !read_only() {
+        let Palette { clear, bold, red, .. } = palette(Stream::Stderr);
+        eprintln!("{bold}{red}{}{clear}", Message::BranchesDisabled);
+    }
+    if git_stashes_is_empty()? {
+        if !args.quiet {
+            println!("{}", Message::NoStashes);
+        }
+        return Ok(());
+    }
+    let mut stashes = selected_stashes(&args)?;
+    if stashes.is_empty() {
+        if !args.quiet {
+            println!("{}", Message::NoMatchingStashes);
+        }
+        return Ok(());
+    }
+    if args.start > 0 {
+        checked_stash_ref(args.start, list_stashes(None)?.len(), "--start")?;
+    }
+    // With --grep, stash@{start} itself may be filtered out, so begin at the
+    // first listed stash at or after it.
+    let Some(mut pos) = stashes.iter().position(|s| s.index >= args.start) else {
+        if !args.quiet {
+            println!("{}", Message::NoMatchingStashes);
+        }
+        return Ok(());
+    };
+    if args.overview && !args.quiet {
+        print_overview(&stashes, Some(pos), None, &args.paths)?;
+    }
+    if args.select {
+        return select_stashes(&stashes, pos, can_save_branch, &args);
+    }
+    if args.tui {
+        #[cfg(unix)]
+        return tui::run(stashes, pos, can_save_branch, &args);
+        #[cfg(not(unix))]
+        return Err(error("--tui is only available on Unix"));
+    }
+    // (hash, message) of each stash dropped this session, most recent last.
+    let mut dropped: Vec<(String, String)> = Vec::new();
+    let mut summary = Summary::default();
+    let mut cache = StashCache::default();
+    // Dropping or branching a stash shifts every later stash down by one, so
+    // only skipping and going back move `pos`; actions that may drop it take
+    // it out of the list and re-show the same position, which now holds the
+    // next stash.
+    while let Some(stash) = stashes.get(pos) {
+        let stash_num = stash.index;
+        if !show_or_report(stash, &args, &mut cache)? {
+            stashes = selected_stashes(&args)?;
+            continue;
+        }
+        let keys = args.keymap.prompt_keys(!dropped.is_empty());
+        let Palette { clear, bold, blue, .. } = palette(Stream::Stdout);
+        let prompt = Message::ActionPrompt { number: pos + 1, count: stashes.len(), keys: &keys };
+        print!("{bold}{blue}{prompt} {clear}");
+        io::stdout().flush()?;
+        let input = match read_key(&args) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!();
+                break;
+            }
+            result => result?,
+        };
+        if TIMED_OUT.swap(false, Ordering::Relaxed) {
+            // Nobody is there to answer a question, so only drop without one,
+            // when dropping is the default and the stash is already applied.
+            let drop = args.default_action() == DefaultAction::Drop && cache.is_applied(stash)?;
+            if drop && drop_stash(stash)? {
+                println!("{}", Message::TimedOutDropped);
+                print_event(&args, "DROPPED", stash, None);
+                dropped.push((stash.hash.clone(), stash.message.clone()));
+                summary.dropped += 1;
+                forget_dropped(&mut stashes, pos, &args)?;
+            } else {
+                println!("{}", Message::TimedOutSkipped);
+                print_event(&args, "SKIPPED", stash, None);
+                pos += 1;
+                summary.skipped += 1;
+            }
+            continue;
+        }
+        let input = input.trim();
+        let empty = input.is_empty();
+        let action = if empty {
+            args.default_action().action()
+        } else if let Some(action) = args.keymap.action(input) {
+            action
+        } else if let Some(line) = script_line_number() {
+            return Err(error(&format!("script line {line}: unknown action '{input}'")));
+        } else {
+            let help = args.keymap.key(Action::Help);
+            println!("{}", Message::UnknownAction { input, help });
+            continue;
+        };
+        if read_only() && action.changes_repository() {
+            println!("{}", Message::ReadOnlyAction);
+            continue;
+        }
+        match action {
+            // Only `--default-action drop` turns empty input into a drop, and
+            // that is easy enough to do by accident to always confirm.
+            Action::Drop => match prompt_drop(stash, empty, &args, &mut cache) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!();
+                    break;
+                }
+                result => {
+                    if result? {
+                        print_event(&args, "DROPPED", stash, None);
+                        dropped.push((stash.hash.clone(), stash.message.clone()));
+                        summary.dropped += 1;
+                    }
+                    forget_dropped(&mut stashes, pos, &args)?;
+                }
+            }
+            Action::DropApplied => match prompt_drop_applied(&stashes[pos..], &args, &mut cache) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!();
+                    break;
+                }
+                result => {
+                    let result = result?;
+                    // Oldest last, so undoing restores the oldest first and
+                    // the stashes come back in their original order.
+                    for stash in result.iter().rev() {
+                        print_event(&args, "DROPPED", stash, None);
+                        dropped.push((stash.hash.clone(), stash.message.clone()));
+                        summary.dropped += 1;
+                    }
+                    let indexes: Vec<u32> = result.iter().map(|stash| stash.index).collect();
+                    remove_stashes(&mut stashes, &indexes);
+                }
+            }
+            Action::Branch => {
+                if let Some(branch) = commit_to_branch(stash, can_save_branch, None, &args)? {
+                    print_event(&args, "BRANCHED", stash, Some(&branch));
+                    summary.branched += 1;
+                }
+                cache.forget_applied();
+                forget_dropped(&mut stashes, pos, &args)?;
+            }
+            Action::BranchKeep => {
+                if let Some(branch) = branch_stash(stash, can_save_branch, None, &args)? {
+                    print_event(&args, "SAVED", stash, Some(&branch));
+                    summary.branched += 1;
+                }
+                cache.forget_applied();
+            }
+            Action::Tag => {
+                if let Some(tag) = tag_stash(stash, can_save_branch, &args)? {
+                    let (stash_name, message) = (stash_ref(stash_num), &stash.message);
+                    println!("{}", Message::Tagged { stash: &stash_name, tag: &tag, message });
+                    print_event(&args, "TAGGED", stash, Some(&tag));
+                    summary.tagged += 1;
+                }
+                cache.forget_applied();
+                forget_dropped(&mut stashes, pos, &args)?;
+            }
+            Action::Commit => {
+                if commit_here(stash, &args)? {
+                    print_event(&args, "COMMITTED", stash, None);
+                    summary.committed += 1;
+                }
+                cache.forget_applied();
+                forget_dropped(&mut stashes, pos, &args)?;
+            }
+            Action::Skip => {
+                print_event(&args, "SKIPPED", stash, None);
+                pos += 1;
+                summary.skipped += 1;
+            }
+            // Everything before `pos` was skipped, so stepping back is always
+            // safe, and un-skips that stash.
+            Action::Previous if pos > 0 => {
+                pos -= 1;
+                summary.skipped = summary.skipped.saturating_sub(1);
+            }
+            Action::Previous => println!("{}", Message::AtFirstStash),
+            Action::Apply => {
+                if apply_stash(stash_num, &args)? {
+                    print_event(&args, "APPLIED", stash, None);
+                    summary.applied += 1;
+                }
+                break;
+            }
+            Action::ApplyOnto => match apply_onto_branch(stash, &args) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!();
+                    break;
+                }
+                result => {
+                    if result? {
+                        print_event(&args, "APPLIED", stash, None);
+                        summary.applied += 1;
+                        break;
+                    }
+                }
+            }
+            Action::Pop | Action::PopIndex => {
+                if pop_stash(stash_num, action == Action::PopIndex, &args)? {
+                    print_event(&args, "POPPED", stash, None);
+                    summary.popped += 1;
+                }
+                cache.forget_applied();
+                forget_dropped(&mut stashes, pos, &args)?;
+            }
+            Action::Export => match prompt_export(stash, &args) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!();
+                    break;
+                }
+                result => result?,
+            }
+            Action::Copy => copy_to_clipboard(stash_num)?,
+            Action::Rename => match prompt_rename(stash) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!();
+                    break;
+                }
+                result => {
+                    result?;
+                    stashes = selected_stashes(&args)?;
+                }
+            }
+            Action::View => {
+                let viewed = if args.difftool {
+                    git_stash_difftool(stash, &args, &mut cache)
+                } else {
+                    git_stash_show(stash, &args, &mut cache)
+                };
+                if let Err(e) = viewed {
+                    report_error(&e);
+                }
+            }
+            Action::Open => {
+                if let Err(e) = open_in_editor(stash_num) {
+                    report_error(&e);
+                }
+            }
+            Action::Info => {
+                if let Err(e) = git_stash_info(stash_num) {
+                    report_error(&e);
+                }
+            }
+            Action::File => match prompt_file(stash, &args) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!();
+                    break;
+                }
+                Err(e) => report_error(&e),
+                Ok(()) => (),
+            }
+            Action::Undo if !dropped.is_empty() => {
+                if let Some((hash, message)) = dropped.pop() {
+                    restore_stash(&hash, &message)?;
+                    summary.dropped = summary.dropped.saturating_sub(1);
+                    // Restored stashes come back as stash@{0}, so it's moved to
+                    // `pos` to come up next, behind the stashes already skipped.
+                    stashes = selected_stashes(&args)?;
+                    if let Some(i) = stashes.iter().position(|stash| stash.hash == hash) {
+                        let restored = stashes.remove(i);
+                        print_event(&args, "RESTORED", &restored, None);
+                        pos = pos.min(stashes.len());
+                        stashes.insert(pos, restored);
+                    }
+                }
+            }
+            Action::Undo => println!("{}", Message::NothingToUndo),
+            Action::Quit if args.confirm_quit => {
+                print!("{} ", Message::QuitUnreviewed { remaining: stashes.len() - pos });
+                io::stdout().flush()?;
+                match read_key(&args) {
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        println!();
+                        break;
+                    }
+                    result => {
+                        if is_yes(&result?) {
+                            break;
+                        }
+                    }
+                }
+            }
+            Action::Quit => { break; }
+            Action::Help if empty && args.quiet => (),
+            Action::Help => args.keymap.print_help(!dropped.is_empty(), &args.paths),
+        }
+    }
+    if !args.quiet {
+        summary.print();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(message: &str) -> Option<String> {
+        first_commit_subject(message.as_bytes()).ok()
+    }
+
+    #[test]
+    fn commit_subject_skips_comments_and_blank_lines() {
+        let message = "# Please enter the commit message\n\n   \nFix the thing\n\nBody\n";
+        assert_eq!(subject(message).as_deref(), Some("Fix the thing"));
+        assert_eq!(subject("#hash at the start\nSubject").as_deref(), Some("Subject"));
+        // Only a `#` right at the start makes a comment.
+        assert_eq!(subject(" # indented").as_deref(), Some(" # indented"));
+    }
+
+    #[test]
+    fn commit_subject_of_an_empty_message_is_an_error() {
+        assert_eq!(subject(""), None);
+        assert_eq!(subject("\n\n# only comments\n"), None);
+    }
+
+    #[test]
+    fn branch_names_keep_only_words() {
+        assert_eq!(sanitize_branch_name("Fix the thing!"), "fix_the_thing");
+        assert_eq!(sanitize_branch_name("  WIP: half-done, again  "), "wip_halfdone_again");
+        assert_eq!(sanitize_branch_name("_leading and trailing_"), "leading_and_trailing");
+        assert_eq!(sanitize_branch_name("release.lock ../x"), "releaselock_x");
+    }
+
+    #[test]
+    fn branch_names_keep_unicode_letters() {
+        assert_eq!(sanitize_branch_name("Überprüfe GRÖSSE"), "überprüfe_grösse");
+        assert_eq!(sanitize_branch_name("修正 バグ"), "修正_バグ");
+    }
+
+    #[test]
+    fn branch_names_from_punctuation_or_nothing_are_empty() {
+        assert_eq!(sanitize_branch_name(""), "");
+        assert_eq!(sanitize_branch_name("!!! ... ?"), "");
+        assert_eq!(sanitize_branch_name("-- / --"), "");
+    }
+
+    #[test]
+    fn yes_takes_y_or_yes_in_any_case() {
+        for answer in ["y", "Y", "yes", "YES", "Yes", " y "] {
+            assert!(is_yes(answer), "{answer:?}");
+        }
+        for answer in ["", "n", "no", "ye", "yess", "x"] {
+            assert!(!is_yes(answer), "{answer:?}");
+        }
+    }
+
+    #[test]
+    fn a_failed_rename_says_how_to_store_each_lost_stash_back() {
+        let lost = [("1111", "On main: older"), ("2222", "On main: don't lose me")];
+        assert_eq!(
+            rename_failed("failed", &lost).to_string(),
+            "failed; to put back the stashes taken out, run in order:\n  \
+            git stash store -m 'On main: older' 1111\n  \
+            git stash store -m 'On main: don'\\''t lose me' 2222",
+        );
+        assert_eq!(rename_failed("failed", &[]).to_string(), "failed");
+    }
+
+    #[test]
+    fn slugs_are_cut_between_words() {
+        assert_eq!(truncate_slug("fix_the_thing", 20), "fix_the_thing");
+        assert_eq!(truncate_slug("fix_the_thing", 13), "fix_the_thing");
+        assert_eq!(truncate_slug("fix_the_thing", 10), "fix_the");
+        // Right before an underscore is already between words.
+        assert_eq!(truncate_slug("fix_the_thing", 7), "fix_the");
+        assert_eq!(truncate_slug("fix_the_thing", 8), "fix_the");
+    }
+
+    #[test]
+    fn slugs_with_one_long_word_are_cut_within_it() {
+        assert_eq!(truncate_slug("refactoring", 5), "refac");
+        // Counted in characters, not bytes.
+        assert_eq!(truncate_slug("überprüfe", 4), "über");
+    }
+}
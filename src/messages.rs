@@ -0,0 +1,423 @@
+//! User-facing messages, kept in one place so they read the same across
+//! actions and can be translated: the prompts, status lines and help. Errors
+//! that end a command are still worded where they're raised, and output meant
+//! for other programs, like `--porcelain` events and `--json`, isn't
+//! translated.
+//!
+//! Each message is a `Message`, written out by its `Display` impl in the
+//! language picked by `language()`. Only English exists so far, and it is
+//! also the fallback for any language without messages of its own.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::{Action, Summary};
+
+/// A language there are messages in.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Language {
+    English,
+}
+
+impl Language {
+    /// The language of a locale name like `en_US.UTF-8`, if there are
+    /// messages in it.
+    fn from_locale(locale: &str) -> Option<Self> {
+        let code = locale.split(['_', '-', '.', '@']).next().unwrap_or_default();
+        match code.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Language::English),
+            _ => None,
+        }
+    }
+}
+
+/// The language to show messages in. As with gettext, the first of
+/// `STASHINBOX_LANG`, `LC_ALL`, `LC_MESSAGES` and `LANG` that is set decides.
+pub(crate) fn language() -> Language {
+    static LANGUAGE: OnceLock<Language> = OnceLock::new();
+    *LANGUAGE.get_or_init(|| {
+        ["STASHINBOX_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|locale| Language::from_locale(&locale))
+            .unwrap_or(Language::English)
+    })
+}
+
+/// Something to tell the user; colors are left to the caller.
+pub(crate) enum Message<'a> {
+    NoStashes,
+    /// The repository is bare, so stashes can only be looked at.
+    ReadOnly,
+    ReadOnlyAction,
+    NoMatchingStashes,
+    /// Local changes rule out the branch and tag actions this session.
+    BranchesDisabled,
+    CommitWithLocalChanges,
+    CommitHereWithLocalChanges,
+    NothingToCommit,
+    CommitFailed { stash: &'a str },
+    StaleTempBranch { branch: &'a str, subject: &'a str },
+    DetachedHead { hash: &'a str },
+    IndexNotRestored { stash: &'a str },
+    /// Staged changes kept the autostash's index from being restored.
+    AutostashUnstaged,
+    Conflict { stash: &'a str },
+    ApplyFailed { stash: &'a str },
+    PopFailed { stash: &'a str },
+    ApplyAllStopped { stash: &'a str, message: &'a str },
+    SwitchWithLocalChanges,
+    NoSuchBranch { branch: &'a str },
+    InvalidBranchName { branch: &'a str },
+    BranchExists { branch: &'a str },
+    NothingApplied,
+    NoneApplied,
+    UnknownAction { input: &'a str, help: char },
+    AtFirstStash,
+    NothingToUndo,
+    NoClipboard { tools: &'a str },
+    ClipboardFailed { tool: &'a str },
+    /// Another git process holds the index lock.
+    IndexLocked { path: &'a str },
+    TimedOut { secs: u64 },
+    TimedOutDropped,
+    TimedOutSkipped,
+    /// The question after `IndexLocked`.
+    RetryLocked,
+    ActionPrompt { number: usize, count: usize, keys: &'a str },
+    ActionHelp { action: Action },
+    PathsOnly { paths: &'a str },
+    QuitUnreviewed { remaining: usize },
+    Summary { summary: &'a Summary },
+    AppliedTag,
+    OldTag,
+    BinaryFilesChanged { count: usize },
+    FileCount { count: usize },
+    NoDiffTool,
+    MadeOnTopOf,
+    IncludesUntracked,
+    DropUnapplied { stash: &'a str },
+    DropConfirm,
+    DropAppliedConfirm { count: usize },
+    NoChangedFiles,
+    PickFile { count: usize },
+    NoSuchFile { input: &'a str, count: usize },
+    ContentOrDiff,
+    DeletedInStash { path: &'a str },
+    RenamePrompt { current: &'a str },
+    /// How to finish or back out of a conflicted apply; `kept` if the stash
+    /// is still in the list.
+    ConflictHelp { stash: &'a str, kept: bool },
+    UndoConflicts,
+    ApplyOntoBranch,
+    DeleteStaleTempBranch,
+    BranchName { proposed: &'a str },
+    Copied { stash: &'a str },
+    EditorFailed,
+    NoEditor,
+    PatchNotWritten { path: &'a str, error: &'a std::io::Error },
+    ExportTo { default: &'a str },
+    Overwrite { path: &'a str },
+    Wrote { path: &'a str },
+    Imported { path: &'a str, stash: &'a str },
+    Dropped { stash: &'a str, message: &'a str },
+    Committed { stash: &'a str, branch: &'a str, message: &'a str },
+    Tagged { stash: &'a str, tag: &'a str, message: &'a str },
+    Applied { stash: &'a str, message: &'a str },
+    Popped { stash: &'a str, message: &'a str },
+    DroppedCount { count: usize, total: usize },
+    BranchedCount { count: usize, total: usize },
+    AppliedCount { count: usize, total: usize },
+    Collected { count: usize, total: usize, branch: &'a str },
+    NotCollected,
+    MarkPrompt { number: usize, count: usize, marked: bool },
+    UnknownKey { input: &'a str },
+    NoneMarked,
+    MarkedAction { count: usize, keys: &'a str },
+    /// Confirms `action` on all `count` marked stashes.
+    MarkedConfirm { action: Action, count: usize },
+    MarkedDone { done: usize, count: usize },
+    /// The key hints along the bottom of the `--tui` screen.
+    TuiHints { drop: char, branch: char, apply: char, pop: char, quit: char },
+    TuiBranchFailed { stash: &'a str },
+    TuiApplyFailed { stash: &'a str },
+    TuiPopFailed { stash: &'a str },
+    WouldRun { command: &'a str },
+    WouldAutostash,
+}
+
+impl Message<'_> {
+    fn english(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::NoStashes => write!(f, "No stashes found."),
+            Message::ReadOnly => write!(
+                f,
+                "This repository is bare, so stashes are read-only: they can be viewed, \
+                exported and copied, but not dropped, branched or applied."
+            ),
+            Message::ReadOnlyAction => {
+                write!(f, "That can't be done in a bare repository, which has no work tree.")
+            }
+            Message::NoMatchingStashes => write!(f, "No matching stashes."),
+            Message::BranchesDisabled => write!(
+                f,
+                "WARNING - Can't backup stashes as branches with local changes.\n\
+                Resolve local changes to backup stashes as branches."
+            ),
+            Message::CommitWithLocalChanges => {
+                write!(f, "ERROR - Can't commit branches with unstaged files!.")
+            }
+            Message::CommitHereWithLocalChanges => write!(
+                f,
+                "ERROR - Can't commit a stash onto this branch with local changes; \
+                they would end up in the commit."
+            ),
+            Message::NothingToCommit => {
+                write!(f, "Stash produces no changes against current HEAD; nothing to commit.")
+            }
+            Message::CommitFailed { stash } => {
+                write!(f, "ERROR - Couldn't commit {stash}; see git's output above.")
+            }
+            Message::StaleTempBranch { branch, subject } => {
+                write!(f, "WARNING - Found {branch}, left by an interrupted run, at \"{subject}\".")
+            }
+            Message::DetachedHead { hash } => {
+                write!(f, "WARNING - HEAD is detached; it will be restored to {hash} afterwards.")
+            }
+            Message::IndexNotRestored { stash } => write!(
+                f,
+                "WARNING - Couldn't restore the index of {stash}; \
+                applying its changes unstaged instead."
+            ),
+            Message::AutostashUnstaged => write!(
+                f,
+                "WARNING - Changes are staged now, so your local changes are restored unstaged."
+            ),
+            Message::Conflict { stash } => {
+                write!(f, "CONFLICT - {stash} did not apply cleanly. Conflicted files:")
+            }
+            Message::ApplyFailed { stash } => {
+                write!(f, "ERROR - Failed to apply {stash}; resolve any conflicts shown above.")
+            }
+            Message::PopFailed { stash } => {
+                write!(f, "ERROR - Failed to pop {stash}; the stash was kept.")
+            }
+            Message::ApplyAllStopped { stash, message } => write!(
+                f,
+                "ERROR - Stopped at {stash} ({message}), which did not apply cleanly."
+            ),
+            Message::SwitchWithLocalChanges => write!(
+                f,
+                "ERROR - Can't switch branches with local changes; commit them or use --autostash."
+            ),
+            Message::NoSuchBranch { branch } => write!(f, "ERROR - No branch named {branch}."),
+            Message::InvalidBranchName { branch } => {
+                write!(f, "'{branch}' isn't a valid branch name; try another.")
+            }
+            Message::BranchExists { branch } => {
+                write!(f, "A branch named {branch} already exists; try another.")
+            }
+            Message::NothingApplied => write!(f, "Nothing was applied; switching back."),
+            Message::NoneApplied => write!(f, "No remaining stashes are already applied."),
+            Message::UnknownAction { input, help } => {
+                write!(f, "Unknown action '{input}', press {help} for help")
+            }
+            Message::AtFirstStash => write!(f, "Already at the first stash."),
+            Message::NothingToUndo => write!(f, "Nothing to undo."),
+            Message::NoClipboard { tools } => {
+                write!(f, "ERROR - No clipboard tool found; looked for {tools}.")
+            }
+            Message::ClipboardFailed { tool } => {
+                write!(f, "ERROR - {tool} failed to copy the diff to the clipboard.")
+            }
+            Message::IndexLocked { path } => write!(
+                f,
+                "ERROR - Another git process is using this repository; {path} exists.\n\
+                If none is running, it crashed and the file can be deleted."
+            ),
+            Message::TimedOut { secs } => write!(f, "No response after {secs}s."),
+            Message::TimedOutDropped => {
+                write!(f, "Dropped it, as drop is the default and it's already applied.")
+            }
+            Message::TimedOutSkipped => write!(f, "Skipped it."),
+            Message::RetryLocked => write!(f, "Retry once it has finished? [Y/n]"),
+            Message::ActionPrompt { number, count, keys } => {
+                write!(f, "Stash {number} of {count} - action on this stash [{keys}]?")
+            }
+            Message::ActionHelp { action } => f.write_str(match action {
+                Action::Drop => "drop this stash",
+                Action::DropApplied => "drop every remaining stash that is already applied",
+                Action::Branch => "commit this stash to a separate branch and delete it",
+                Action::BranchKeep => "commit this stash to a separate branch but keep it",
+                Action::Tag => "commit this stash under an annotated stash/ tag and delete it",
+                Action::Commit => "commit this stash onto the current branch and delete it",
+                Action::Skip => "take no action on this stash",
+                Action::Previous => "previous; go back to the last stash skipped",
+                Action::Apply => "apply; apply the stash and take no further action",
+                Action::ApplyOnto => {
+                    "apply onto; switch to another branch and apply the stash there"
+                }
+                Action::Pop => "pop; apply the stash and drop it",
+                Action::PopIndex => "pop index; pop the stash, restaging what was staged",
+                Action::Export => "export this stash to a patch file",
+                Action::Copy => "yank; copy this stash's diff to the clipboard",
+                Action::Rename => "rename; edit this stash's message",
+                Action::View => "view the full diff of this stash",
+                Action::Open => "open; read the full diff of this stash in your editor",
+                Action::Info => "info; show this stash's commit, parents and reflog entry",
+                Action::File => "file; pick a changed file and show its stashed content or diff",
+                Action::Undo => "undo; restore the most recently dropped stash",
+                Action::Quit => "quit; take no further action on remaining stashes",
+                Action::Help => "print help",
+            }),
+            Message::PathsOnly { paths } => {
+                write!(f, "Only changes to {paths} are shown; actions affect the whole stash.")
+            }
+            Message::QuitUnreviewed { remaining: 1 } => {
+                write!(f, "1 stash remains unreviewed. Quit anyway? [y/N]")
+            }
+            Message::QuitUnreviewed { remaining } => {
+                write!(f, "{remaining} stashes remain unreviewed. Quit anyway? [y/N]")
+            }
+            Message::Summary { summary } => write!(
+                f,
+                "Dropped {}, branched {}, tagged {}, committed {}, applied {}, popped {}, \
+                skipped {}.",
+                summary.dropped, summary.branched, summary.tagged, summary.committed,
+                summary.applied, summary.popped, summary.skipped,
+            ),
+            Message::AppliedTag => write!(f, "[already applied]"),
+            Message::OldTag => write!(f, "OLD"),
+            Message::BinaryFilesChanged { count: 1 } => write!(f, "1 binary file changed:"),
+            Message::BinaryFilesChanged { count } => write!(f, "{count} binary files changed:"),
+            Message::FileCount { count: 1 } => write!(f, "1 file"),
+            Message::FileCount { count } => write!(f, "{count} files"),
+            Message::NoDiffTool => write!(f, "No diff.tool configured, showing the diff instead."),
+            Message::MadeOnTopOf => write!(f, "Made on top of:"),
+            Message::IncludesUntracked => write!(f, "Includes untracked files."),
+            Message::DropUnapplied { stash } => {
+                write!(f, "{stash} may not be applied. Drop anyway? [y/N]")
+            }
+            Message::DropConfirm => write!(f, "Drop this stash? [y/N]"),
+            Message::DropAppliedConfirm { count: 1 } => write!(f, "Drop this stash? [y/N]"),
+            Message::DropAppliedConfirm { .. } => write!(f, "Drop these stashes? [y/N]"),
+            Message::NoChangedFiles => write!(f, "No changed files."),
+            Message::PickFile { count } => write!(f, "Show which file [1-{count}]?"),
+            Message::NoSuchFile { input, count } => {
+                write!(f, "No file {input}; expected a number from 1 to {count}.")
+            }
+            Message::ContentOrDiff => write!(f, "Show its [c]ontent in the stash or its [d]iff?"),
+            Message::DeletedInStash { path } => write!(f, "{path} is deleted in the stash."),
+            Message::RenamePrompt { current } => write!(f, "New message [{current}]:"),
+            Message::ConflictHelp { stash, kept } => {
+                writeln!(
+                    f,
+                    "To resolve, edit those files and mark them resolved with `git add <file>`."
+                )?;
+                if *kept {
+                    writeln!(f, "The stash was kept; `git stash drop {stash}` once you're done.")?;
+                }
+                write!(f, "To undo the apply instead, run `git reset --merge`.")
+            }
+            Message::UndoConflicts => write!(f, "Undo it now? [y/N]"),
+            Message::ApplyOntoBranch => write!(f, "Apply onto branch:"),
+            Message::DeleteStaleTempBranch => {
+                write!(f, "Delete it? Its stash was not dropped. [y/N]")
+            }
+            Message::BranchName { proposed } => write!(f, "Branch name [{proposed}]:"),
+            Message::Copied { stash } => write!(f, "Copied the diff of {stash} to the clipboard."),
+            Message::EditorFailed => write!(f, "The editor exited with an error."),
+            Message::NoEditor => {
+                write!(f, "ERROR - No editor configured; set core.editor or $EDITOR.")
+            }
+            Message::PatchNotWritten { path, error } => {
+                write!(f, "ERROR - Couldn't write the patch to {path}: {error}.")
+            }
+            Message::ExportTo { default } => write!(f, "Export to [{default}]:"),
+            Message::Overwrite { path } => write!(f, "{path} already exists. Overwrite? [y/N]"),
+            Message::Wrote { path } => write!(f, "Wrote {path}"),
+            Message::Imported { path, stash } => write!(f, "Imported {path} as {stash}"),
+            Message::Dropped { stash, message } => write!(f, "Dropped {stash}: {message}"),
+            Message::Committed { stash, branch, message } => {
+                write!(f, "Committed {stash} to {branch}: {message}")
+            }
+            Message::Tagged { stash, tag, message } => {
+                write!(f, "Tagged {stash} as {tag}: {message}")
+            }
+            Message::Applied { stash, message } => write!(f, "Applied {stash}: {message}"),
+            Message::Popped { stash, message } => write!(f, "Popped {stash}: {message}"),
+            Message::DroppedCount { count, total } => {
+                write!(f, "Dropped {count} of {total} stashes.")
+            }
+            Message::BranchedCount { count, total } => {
+                write!(f, "Branched {count} of {total} stashes.")
+            }
+            Message::AppliedCount { count, total } => {
+                write!(f, "Applied {count} of {total} stashes.")
+            }
+            Message::Collected { count, total, branch } => {
+                write!(f, "Collected {count} of {total} stashes onto {branch}.")
+            }
+            Message::NotCollected => {
+                write!(f, "These stashes did not apply cleanly and were kept:")
+            }
+            Message::MarkPrompt { number, count, marked } => {
+                let state = if *marked { "marked" } else { "not marked" };
+                write!(
+                    f,
+                    "Stash {number} of {count} ({state}) - \
+                    mark it [space/x toggles, enter/s moves on, k goes back, q is done]?"
+                )
+            }
+            Message::UnknownKey { input } => write!(f, "Unknown key '{input}'"),
+            Message::NoneMarked => write!(f, "No stashes marked."),
+            Message::MarkedAction { count: 1, keys } => {
+                write!(f, "Action on the 1 marked stash [{keys}]?")
+            }
+            Message::MarkedAction { count, keys } => {
+                write!(f, "Action on the {count} marked stashes [{keys}]?")
+            }
+            Message::MarkedConfirm { action, count } => {
+                let question = match action {
+                    Action::Drop => "Drop",
+                    Action::Branch => "Commit to branches and drop",
+                    Action::Tag => "Tag and drop",
+                    _ => "Apply",
+                };
+                let noun = if *count == 1 { "stash" } else { "stashes" };
+                write!(f, "{question} {count} {noun}? [y/N]")
+            }
+            Message::MarkedDone { done, count: 1 } => {
+                write!(f, "Done with {done} of 1 marked stash.")
+            }
+            Message::MarkedDone { done, count } => {
+                write!(f, "Done with {done} of {count} marked stashes.")
+            }
+            Message::TuiHints { drop, branch, apply, pop, quit } => write!(
+                f,
+                "\u{2191}\u{2193} select  PgUp/PgDn scroll  \
+                {drop} drop  {branch} branch  {apply} apply  {pop} pop  {quit} quit"
+            ),
+            Message::TuiBranchFailed { stash } => {
+                write!(f, "Couldn't branch {stash}; git's output is shown after quitting")
+            }
+            Message::TuiApplyFailed { stash } => {
+                write!(f, "{stash} didn't apply cleanly; git's output is shown after quitting")
+            }
+            Message::TuiPopFailed { stash } => {
+                write!(f, "{stash} didn't apply cleanly and was kept")
+            }
+            Message::WouldRun { command } => write!(f, "would run: {command}"),
+            Message::WouldAutostash => write!(f, "would autostash local changes"),
+        }
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match language() {
+            Language::English => self.english(f),
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! The `--tui` front end: the stash list on the left, the selected stash's
+//! diff on the right and the keys along the bottom, drawn with plain ANSI
+//! escapes over the same operations the prompt uses.
+//!
+//! Operations that run git with its output showing, like applying, step out
+//! of the TUI's screen while they run, so what git says is left in the
+//! terminal's normal scrollback for after quitting.
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Mutex, Once};
+
+use crate::{
+    apply_stash, commit_to_branch, drop_stash, error, forget_dropped, palette, pop_stash,
+    print_event, stash_diff, stash_diff_target, stash_ref, stty, sys, Action, Args, Message,
+    Palette, Stash, StashCache, Stream, Summary,
+};
+
+/// The terminal settings to put back once the TUI lets go of the screen.
+static SAVED_TERMINAL: Mutex<Option<String>> = Mutex::new(None);
+
+/// The TUI's hold on the terminal: raw input on the alternate screen, with
+/// the cursor hidden. Dropping it, or panicking, puts everything back.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(["-g"])?;
+        stty(["-icanon", "-echo", "-isig", "min", "1"])?;
+        *SAVED_TERMINAL.lock().unwrap_or_else(|e| e.into_inner()) = Some(saved);
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let default = std::panic::take_hook();
+            // Restore first, so the panic message lands on the normal screen.
+            std::panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                default(info);
+            }));
+        });
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Screen)
+    }
+
+    /// Give the terminal back while `f` runs, then take it again.
+    fn suspended<T>(&self, f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        restore_terminal();
+        let result = f();
+        std::mem::forget(Screen::enter()?);
+        result
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let saved = SAVED_TERMINAL.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(saved) = saved {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty([&saved]);
+    }
+}
+
+enum Input {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Key(char),
+    /// An escape sequence for some other key.
+    Other,
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = 0u8;
+    match unsafe { sys::read(0, (&mut byte as *mut u8).cast(), 1) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ => Ok(Some(byte)),
+    }
+}
+
+/// Whether another byte arrives within a moment, as the rest of an escape
+/// sequence would, rather than the escape key on its own.
+fn more_input() -> io::Result<bool> {
+    let mut fd = sys::PollFd { fd: 0, events: sys::POLLIN, revents: 0 };
+    match unsafe { sys::poll(&mut fd, 1, 50) } {
+        -1 => Err(io::Error::last_os_error()),
+        ready => Ok(ready > 0),
+    }
+}
+
+/// The next keypress, or `None` at end of input.
+fn read_input() -> io::Result<Option<Input>> {
+    let Some(byte) = read_byte()? else {
+        return Ok(None);
+    };
+    if byte != 0x1b {
+        return Ok(Some(Input::Key(char::from(byte))));
+    }
+    if !more_input()? {
+        return Ok(Some(Input::Other));
+    }
+    // Arrows are `ESC [ A` or `ESC O A`; page keys are `ESC [ 5 ~`.
+    let input = match (read_byte()?, read_byte()?) {
+        (Some(b'[' | b'O'), Some(b'A')) => Input::Up,
+        (Some(b'[' | b'O'), Some(b'B')) => Input::Down,
+        (Some(b'['), Some(digit @ (b'5' | b'6'))) => {
+            read_byte()?;
+            if digit == b'5' { Input::PageUp } else { Input::PageDown }
+        }
+        _ => Input::Other,
+    };
+    Ok(Some(input))
+}
+
+/// `text` cut or padded to exactly `width` columns, with tabs expanded.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut columns = 0;
+    for c in text.chars() {
+        if columns == width {
+            break;
+        }
+        if c == '\t' {
+            let spaces = (8 - columns % 8).min(width - columns);
+            fitted.extend(std::iter::repeat_n(' ', spaces));
+            columns += spaces;
+        } else if !c.is_control() {
+            fitted.push(c);
+            columns += 1;
+        }
+    }
+    fitted.extend(std::iter::repeat_n(' ', width - columns));
+    fitted
+}
+
+/// The terminal's size as (rows, columns).
+fn terminal_size() -> io::Result<(usize, usize)> {
+    let size = stty(["size"])?;
+    let mut parts = size.split_whitespace().map(|n| n.parse().unwrap_or_default());
+    Ok((parts.next().unwrap_or_default(), parts.next().unwrap_or_default()))
+}
+
+/// The stash's patch as plain lines; coloring is left to `draw`.
+fn diff_lines(stash: &Stash, paths: &[String]) -> io::Result<Vec<String>> {
+    let mut show = stash_diff(paths);
+    show.args(["-p", "--color=never"]);
+    stash_diff_target(&mut show, stash.index, paths);
+    let output = show.output()?;
+    if !output.status.success() {
+        return Err(error(&format!("failed to show {}", stash_ref(stash.index))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+struct Tui<'a> {
+    args: &'a Args,
+    stashes: Vec<Stash>,
+    selected: usize,
+    /// The first stash shown, once there are more than fit.
+    list_offset: usize,
+    /// How far the diff is scrolled down, in lines.
+    scroll: usize,
+    /// The result of the last action, shown above the key hints.
+    status: String,
+    diffs: HashMap<String, Vec<String>>,
+}
+
+impl Tui<'_> {
+    fn draw(&mut self) -> io::Result<()> {
+        let (rows, columns) = terminal_size()?;
+        let mut frame = String::from("\x1b[H");
+        if rows < 5 || columns < 40 {
+            frame.push_str("\x1b[2JTerminal too small");
+            print!("{frame}");
+            return io::stdout().flush();
+        }
+        let Palette { clear, bold, red, green, yellow, blue } = palette(Stream::Stdout);
+        let body = rows - 2;
+        let left = (columns / 3).clamp(20, 50);
+        let right = columns - left - 1;
+
+        if self.selected < self.list_offset {
+            self.list_offset = self.selected;
+        } else if self.selected >= self.list_offset + body {
+            self.list_offset = self.selected + 1 - body;
+        }
+        let stash = &self.stashes[self.selected];
+        if !self.diffs.contains_key(&stash.hash) {
+            let lines = diff_lines(stash, &self.args.paths)?;
+            self.diffs.insert(stash.hash.clone(), lines);
+        }
+        let mut pane = vec![
+            (format!("{bold}{yellow}"), format!("{} {}", stash_ref(stash.index), stash.subject())),
+            (String::new(), format!("{}, {} by {}", stash.branch, stash.age, stash.author)),
+            (String::new(), String::new()),
+        ];
+        for line in &self.diffs[&stash.hash] {
+            let color = if line.starts_with("diff --git") {
+                bold
+            } else if line.starts_with("@@") {
+                blue
+            } else if line.starts_with('+') && !line.starts_with("+++") {
+                green
+            } else if line.starts_with('-') && !line.starts_with("---") {
+                red
+            } else {
+                ""
+            };
+            pane.push((color.to_string(), line.clone()));
+        }
+        self.scroll = self.scroll.min(pane.len().saturating_sub(body));
+
+        for row in 0..body {
+            let list_entry = self.stashes.get(self.list_offset + row).map(|stash| {
+                let text = format!(" {} {}", stash_ref(stash.index), stash.subject());
+                let highlight = self.list_offset + row == self.selected;
+                // Reverse video isn't color, so it stays even with color off.
+                let (on, off) = if highlight { ("\x1b[7m", "\x1b[0m") } else { ("", "") };
+                format!("{on}{}{off}", fit(&text, left))
+            });
+            let (color, text) = pane.get(self.scroll + row).cloned().unwrap_or_default();
+            frame.push_str(&format!(
+                "{}\u{2502}{color}{}{clear}\n",
+                list_entry.unwrap_or_else(|| fit("", left)),
+                fit(&text, right),
+            ));
+        }
+        frame.push_str(&format!("{}\n", fit(&self.status, columns)));
+        let keys = &self.args.keymap;
+        let hints = Message::TuiHints {
+            drop: keys.key(Action::Drop),
+            branch: keys.key(Action::Branch),
+            apply: keys.key(Action::Apply),
+            pop: keys.key(Action::Pop),
+            quit: keys.key(Action::Quit),
+        }
+        .to_string();
+        frame.push_str(&format!("\x1b[7m{}\x1b[0m", fit(&hints, columns)));
+        print!("{frame}");
+        io::stdout().flush()
+    }
+
+    /// Show `question` in the status line and read a y/N answer.
+    fn confirm(&mut self, question: String) -> io::Result<bool> {
+        self.status = question;
+        self.draw()?;
+        let answer = matches!(read_input()?, Some(Input::Key('y' | 'Y')));
+        self.status.clear();
+        Ok(answer)
+    }
+
+    /// Take the selected stash out of the list if an action dropped it,
+    /// keeping the selection in range.
+    fn forget_dropped(&mut self) -> io::Result<()> {
+        forget_dropped(&mut self.stashes, self.selected, self.args)?;
+        self.selected = self.selected.min(self.stashes.len().saturating_sub(1));
+        self.scroll = 0;
+        Ok(())
+    }
+}
+
+/// Run the TUI over `stashes`, starting at `pos`, until the user quits or
+/// no stashes are left.
+pub(crate) fn run(
+    stashes: Vec<Stash>,
+    pos: usize,
+    can_save_branch: bool,
+    args: &Args,
+) -> io::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(error("--tui needs a terminal"));
+    }
+    let mut tui = Tui {
+        args,
+        stashes,
+        selected: pos,
+        list_offset: 0,
+        scroll: 0,
+        status: String::new(),
+        diffs: HashMap::new(),
+    };
+    let mut summary = Summary::default();
+    let mut cache = StashCache::default();
+    let screen = Screen::enter()?;
+    while !tui.stashes.is_empty() {
+        tui.draw()?;
+        let Some(input) = read_input()? else {
+            break;
+        };
+        let page = terminal_size()?.0.saturating_sub(3).max(1);
+        let key = match input {
+            Input::Up => Some(Action::Previous),
+            Input::Down => Some(Action::Skip),
+            Input::PageUp => {
+                tui.scroll = tui.scroll.saturating_sub(page);
+                None
+            }
+            Input::PageDown => {
+                tui.scroll += page;
+                None
+            }
+            // Ctrl-C arrives as a byte here, since raw mode turns off signals.
+            Input::Key('\x03') => Some(Action::Quit),
+            Input::Key(key) => args.keymap.action(&key.to_string()),
+            Input::Other => None,
+        };
+        let stash = &tui.stashes[tui.selected].clone();
+        let name = stash_ref(stash.index);
+        match key {
+            Some(Action::Previous) if tui.selected > 0 => {
+                tui.selected -= 1;
+                tui.scroll = 0;
+            }
+            Some(Action::Skip) if tui.selected + 1 < tui.stashes.len() => {
+                tui.selected += 1;
+                tui.scroll = 0;
+            }
+            Some(Action::Drop) => {
+                let sure = args.force || cache.is_applied(stash)?
+                    || tui.confirm(Message::DropUnapplied { stash: &name }.to_string())?;
+                if sure {
+                    let message = stash.message.clone();
+                    if screen.suspended(|| drop_stash(stash))? {
+                        tui.status = Message::Dropped { stash: &name, message: &message }
+                            .to_string();
+                        summary.dropped += 1;
+                        print_event(args, "DROPPED", stash, None);
+                    }
+                    tui.forget_dropped()?;
+                }
+            }
+            Some(Action::Branch) => {
+                let branch = screen.suspended(|| {
+                    commit_to_branch(stash, can_save_branch, Some(stash.subject()), args)
+                })?;
+                tui.status = match branch {
+                    Some(branch) => {
+                        summary.branched += 1;
+                        print_event(args, "BRANCHED", stash, Some(&branch));
+                        let message = &stash.message;
+                        Message::Committed { stash: &name, branch: &branch, message }.to_string()
+                    }
+                    None => Message::TuiBranchFailed { stash: &name }.to_string(),
+                };
+                cache.forget_applied();
+                tui.forget_dropped()?;
+            }
+            Some(Action::Apply) => {
+                let applied = screen.suspended(|| apply_stash(stash.index, args))?;
+                tui.status = if applied {
+                    summary.applied += 1;
+                    print_event(args, "APPLIED", stash, None);
+                    Message::Applied { stash: &name, message: &stash.message }.to_string()
+                } else {
+                    Message::TuiApplyFailed { stash: &name }.to_string()
+                };
+                cache.forget_applied();
+            }
+            Some(Action::Pop) => {
+                let popped = screen.suspended(|| pop_stash(stash.index, false, args))?;
+                tui.status = if popped {
+                    summary.popped += 1;
+                    print_event(args, "POPPED", stash, None);
+                    Message::Popped { stash: &name, message: &stash.message }.to_string()
+                } else {
+                    Message::TuiPopFailed { stash: &name }.to_string()
+                };
+                cache.forget_applied();
+                tui.forget_dropped()?;
+            }
+            Some(Action::Quit) => break,
+            _ => (),
+        }
+    }
+    drop(screen);
+    if !args.quiet {
+        summary.print();
+    }
+    Ok(())
+}
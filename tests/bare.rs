@@ -0,0 +1,57 @@
+//! Reviewing stashes in a bare repository, where they can only be looked at.
+
+mod common;
+
+use common::TempRepo;
+
+/// A repository with a stash, made bare, so that running in its `.git`
+/// directory finds no work tree.
+fn bare_repo() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.git(["config", "core.bare", "true"]);
+    repo
+}
+
+#[test]
+fn bare_repository_shows_stashes_but_refuses_to_drop() {
+    let repo = bare_repo();
+    let script = repo.script(&["d", "q"]);
+
+    let output = repo.run_in(".git", ["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("stashes are read-only"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("+a changed"), "{stdout}");
+    assert!(stdout.contains("[s,k,e,y,v,f,o,i,q,?]"), "{stdout}");
+    assert!(stdout.contains("That can't be done in a bare repository"), "{stdout}");
+    let reflog = repo.git(["--git-dir=.git", "log", "-g", "--format=%gs", "refs/stash"]);
+    assert_eq!(reflog, "On main: Change a\n");
+}
+
+#[test]
+fn bare_repository_lists_and_exports() {
+    let repo = bare_repo();
+    let patch = repo.path.join("change.patch");
+    let script = repo.script(&["e", patch.to_str().expect("utf-8 path"), "q"]);
+
+    let output = repo.run_in(".git", ["list"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "stash@{0}: On main: Change a\n");
+
+    let output = repo.run_in(".git", ["--script", &script]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let patch = std::fs::read_to_string(patch).expect("read patch");
+    assert!(patch.contains("+a changed"), "{patch}");
+}
+
+#[test]
+fn bare_repository_refuses_batch_operations() {
+    let repo = bare_repo();
+
+    let output = repo.run_in(".git", ["--drop-applied"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("batch operations can't be used in a bare repository"), "{stderr}");
+}
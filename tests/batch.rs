@@ -0,0 +1,580 @@
+//! The non-interactive modes, run through the binary.
+
+mod common;
+
+use common::TempRepo;
+
+#[test]
+fn drop_applied_drops_only_applied_stashes() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "change b");
+    repo.git(["stash", "apply", "-q", "stash@{1}"]);
+
+    let output = repo.run(["--drop-applied"]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.stashes(), ["change b"]);
+    assert_eq!(repo.read("a.txt"), "a changed\n");
+}
+
+#[test]
+fn drop_applied_keeps_everything_when_nothing_is_applied() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "change a");
+
+    let output = repo.run(["--drop-applied"]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.stashes(), ["change a"]);
+}
+
+#[test]
+fn drop_applied_keeps_a_stash_of_only_untracked_files() {
+    let repo = TempRepo::new();
+    repo.write("new.txt", "new\n");
+    repo.git(["stash", "push", "-q", "-u", "-m", "untracked only"]);
+
+    let output = repo.run(["--drop-applied"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["untracked only"]);
+}
+
+#[test]
+fn branch_all_commits_each_stash_to_its_own_branch() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(repo.stashes().is_empty());
+    assert_eq!(repo.branches(), ["main", "stash/change_a", "stash/change_b"]);
+    assert_eq!(repo.git(["show", "stash/change_a:a.txt"]), "a changed\n");
+    assert_eq!(repo.git(["log", "-1", "--format=%s", "stash/change_b"]), "Change b\n");
+    // The checkout is left as it was.
+    assert_eq!(repo.git(["symbolic-ref", "--short", "HEAD"]), "main\n");
+    assert_eq!(repo.read("a.txt"), "a\n");
+}
+
+#[test]
+fn branch_all_refuses_with_local_changes() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.write("README", "local edit\n");
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(!output.status.success());
+    assert_eq!(repo.stashes(), ["Change a"]);
+    assert_eq!(repo.branches(), ["main"]);
+}
+
+#[test]
+fn branch_all_with_worktree_ignores_local_changes() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.write("README", "local edit\n");
+
+    let output = repo.run(["--branch-all", "--worktree"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(repo.stashes().is_empty());
+    assert_eq!(repo.git(["show", "stash/change_a:a.txt"]), "a changed\n");
+    assert_eq!(repo.read("README"), "local edit\n");
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+
+    let output = repo.run(["--branch-all", "--dry-run"]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("would run: git stash drop"));
+    assert_eq!(repo.stashes(), ["Change a"]);
+    assert_eq!(repo.branches(), ["main"]);
+}
+
+#[test]
+fn branch_all_with_autostash_restores_local_changes() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+    repo.write("staged.txt", "staged\n");
+    repo.git(["add", "staged.txt"]);
+    repo.write("README", "local edit\n");
+    repo.write("untracked.txt", "untracked\n");
+    let status = repo.git(["status", "--porcelain"]);
+
+    let output = repo.run(["--branch-all", "--autostash"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(repo.stashes().is_empty());
+    assert_eq!(repo.branches(), ["main", "stash/change_a", "stash/change_b"]);
+    assert_eq!(repo.git(["show", "stash/change_b:b.txt"]), "b changed\n");
+    assert_eq!(repo.git(["status", "--porcelain"]), status);
+    assert_eq!(repo.read("README"), "local edit\n");
+    assert_eq!(repo.git(["for-each-ref", "refs/stash-inbox"]), "");
+}
+
+#[test]
+fn collect_branch_with_autostash_restores_local_changes() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+    repo.write("README", "local edit\n");
+
+    let output = repo.run(["--collect-branch", "collected", "--autostash"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(repo.stashes().is_empty());
+    assert_eq!(repo.git(["show", "collected:b.txt"]), "b changed\n");
+    assert_eq!(repo.read("README"), "local edit\n");
+}
+
+#[test]
+fn branch_all_fails_after_finishing_if_a_stash_fails() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+    // Commit exactly what the older stash holds, leaving it nothing to add.
+    repo.write("a.txt", "a changed\n");
+    repo.git(["commit", "-q", "-am", "commit a"]);
+
+    let output = repo.run(["--branch-all"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(repo.stashes(), ["Change a"]);
+    assert_eq!(repo.branches(), ["main", "stash/change_b"]);
+}
+
+#[cfg(unix)]
+fn failing_pre_commit_hook(repo: &TempRepo) {
+    use std::os::unix::fs::PermissionsExt;
+    let hooks = repo.path.join(".git").join("hooks");
+    std::fs::create_dir_all(&hooks).expect("create hooks dir");
+    let hook = hooks.join("pre-commit");
+    std::fs::write(&hook, "#!/bin/sh\necho 'pre-commit says no' >&2\nexit 1\n")
+        .expect("write hook");
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755))
+        .expect("make hook executable");
+}
+
+#[cfg(unix)]
+#[test]
+fn branch_all_skips_hooks_by_default() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    failing_pre_commit_hook(&repo);
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.branches(), ["main", "stash/change_a"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn verify_runs_hooks_and_keeps_the_stash_when_they_fail() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    failing_pre_commit_hook(&repo);
+
+    let output = repo.run(["--branch-all", "--verify"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pre-commit says no"));
+    assert_eq!(repo.stashes(), ["Change a"]);
+    assert_eq!(repo.branches(), ["main"]);
+    assert_eq!(repo.git(["status", "--porcelain"]), "");
+}
+
+/// A stash made with `--include-untracked`, holding a change to a tracked
+/// file and new files at the top level and in a new directory.
+fn untracked_stash() -> TempRepo {
+    let repo = TempRepo::new();
+    std::fs::create_dir_all(repo.path.join("sub")).expect("create sub");
+    repo.write("sub/tracked.txt", "tracked\n");
+    repo.git(["add", "sub"]);
+    repo.git(["commit", "-q", "-m", "add sub"]);
+    repo.write("README", "base\nchanged\n");
+    repo.write("new.txt", "new\n");
+    std::fs::create_dir_all(repo.path.join("newdir")).expect("create newdir");
+    repo.write("newdir/file.txt", "in new dir\n");
+    repo.git(["stash", "push", "-q", "-u", "-m", "With untracked"]);
+    repo
+}
+
+#[test]
+fn branch_all_commits_untracked_files() {
+    let repo = untracked_stash();
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let files = repo.git(["show", "--name-only", "--format=", "stash/with_untracked"]);
+    assert_eq!(files, "README\nnew.txt\nnewdir/file.txt\n");
+    assert_eq!(repo.git(["status", "--porcelain"]), "");
+}
+
+#[test]
+fn branch_all_from_a_subdirectory_commits_the_whole_stash() {
+    let repo = untracked_stash();
+
+    let output = repo.run_in("sub", ["--branch-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let files = repo.git(["show", "--name-only", "--format=", "stash/with_untracked"]);
+    assert_eq!(files, "README\nnew.txt\nnewdir/file.txt\n");
+    assert_eq!(repo.git(["status", "--porcelain"]), "");
+}
+
+#[test]
+fn apply_all_applies_every_stash_and_keeps_them() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+
+    let output = repo.run(["--apply-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Applied 2 of 2 stashes."));
+    assert_eq!(repo.read("a.txt"), "a changed\n");
+    assert_eq!(repo.read("b.txt"), "b changed\n");
+    assert_eq!(repo.stashes(), ["Change b", "Change a"]);
+}
+
+#[test]
+fn apply_all_stops_at_the_first_stash_that_fails() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "first\n", "First");
+    repo.stash("a.txt", "a\n", "second\n", "Second");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+
+    let output = repo.run(["--apply-all"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Stopped at stash@{1}"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Applied 1 of 3 stashes."));
+    assert_eq!(repo.read("a.txt"), "first\n");
+    assert_eq!(repo.read("b.txt"), "b\n");
+}
+
+#[test]
+fn apply_all_index_with_autostash_restores_local_changes() {
+    let repo = TempRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.git(["add", "a.txt"]);
+    repo.git(["commit", "-q", "-m", "add a.txt"]);
+    repo.write("a.txt", "a changed\n");
+    repo.git(["add", "a.txt"]);
+    repo.git(["stash", "push", "-q", "-m", "Change a"]);
+    repo.write("README", "local edit\n");
+    repo.git(["add", "README"]);
+
+    let output = repo.run(["--apply-all", "--autostash", "--index"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // The stash's change comes back staged, and the local change unstaged
+    // since `--index` can't restore it on top of that.
+    assert_eq!(repo.git(["status", "--porcelain"]), " M README\nM  a.txt\n");
+    assert_eq!(repo.read("README"), "local edit\n");
+    assert_eq!(repo.git(["for-each-ref", "refs/stash-inbox"]), "");
+}
+
+#[test]
+fn apply_all_refuses_with_local_changes() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.write("README", "local edit\n");
+
+    let output = repo.run(["--apply-all"]);
+
+    assert!(!output.status.success());
+    assert_eq!(repo.read("a.txt"), "a\n");
+}
+
+#[test]
+fn range_limits_a_batch_to_part_of_the_stash_list() {
+    let repo = TempRepo::new();
+    for name in ["a", "b", "c", "d"] {
+        repo.stash(&format!("{name}.txt"), "base\n", "changed\n", &format!("Change {name}"));
+    }
+
+    // Newest first: stash@{1} is "Change c" and stash@{2} is "Change b".
+    let output = repo.run(["--branch-all", "--range", "1..3"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.branches(), ["main", "stash/change_b", "stash/change_c"]);
+    assert_eq!(repo.stashes(), ["Change d", "Change a"]);
+}
+
+#[test]
+fn range_past_the_end_is_an_error() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+
+    let output = repo.run(["--branch-all", "--range", "0..2"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "--range 0..2: stash index 1 out of range; only 1 stash exists"
+    ));
+    assert_eq!(repo.stashes(), ["Change a"]);
+}
+
+fn stash_by_someone_else(repo: &TempRepo) {
+    repo.write("a.txt", "a\n");
+    repo.git(["add", "a.txt"]);
+    repo.git(["commit", "-q", "-m", "add a.txt"]);
+    repo.write("a.txt", "a changed\n");
+    repo.git([
+        "-c", "user.name=Stasher", "-c", "user.email=stasher@example.com",
+        "stash", "push", "-q", "-m", "Change a",
+    ]);
+}
+
+#[test]
+fn rescue_commits_are_authored_by_the_current_user() {
+    let repo = TempRepo::new();
+    stash_by_someone_else(&repo);
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let idents = repo.git(["log", "-1", "--format=%an <%ae>%n%cn <%ce>", "stash/change_a"]);
+    assert_eq!(idents, "Test <test@example.com>\nTest <test@example.com>\n");
+}
+
+#[test]
+fn stash_author_credits_whoever_made_the_stash() {
+    let repo = TempRepo::new();
+    stash_by_someone_else(&repo);
+
+    let output = repo.run(["--branch-all", "--stash-author"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let idents = repo.git(["log", "-1", "--format=%an <%ae>%n%cn <%ce>", "stash/change_a"]);
+    assert_eq!(idents, "Stasher <stasher@example.com>\nTest <test@example.com>\n");
+}
+
+#[test]
+fn missing_identity_is_a_clear_error() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.git(["config", "--unset", "user.name"]);
+    repo.git(["config", "--unset", "user.email"]);
+    // Don't let git guess an identity from the host name.
+    repo.git(["config", "user.useConfigOnly", "true"]);
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("git config user.name"));
+    assert_eq!(repo.stashes(), ["Change a"]);
+    assert_eq!(repo.branches(), ["main"]);
+}
+
+fn stashes_from_three_months() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.stash_at("2024-01-10T12:00:00+00:00", "a.txt", "a\n", "a changed\n", "january");
+    repo.stash_at("2024-02-10T12:00:00+00:00", "b.txt", "b\n", "b changed\n", "february");
+    repo.stash_at("2024-03-10T12:00:00+00:00", "c.txt", "c\n", "c changed\n", "march");
+    repo
+}
+
+fn listed(output: &std::process::Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn since_and_until_keep_stashes_within_the_dates() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["list", "--since", "2024-02-01", "--until", "2024-03-10"]);
+
+    assert_eq!(listed(&output), "stash@{0}: On main: march\nstash@{1}: On main: february\n");
+}
+
+#[test]
+fn until_with_a_time_stops_within_the_day() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["list", "--until", "2024-02-10 11:59"]);
+
+    assert_eq!(listed(&output), "stash@{2}: On main: january\n");
+}
+
+#[test]
+fn since_combines_with_grep_and_start() {
+    let repo = stashes_from_three_months();
+    let script = repo.script(&["d"]);
+    // Only february matches all of these, and it's stash@{1}.
+    let filters = ["--since", "2024-01-15", "--grep", "e|j", "--force", "--script", &script];
+
+    let output = repo.run(filters.into_iter().chain(["--start", "2"]));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No matching stashes."));
+    assert_eq!(repo.stashes(), ["march", "february", "january"]);
+
+    let output = repo.run(filters.into_iter().chain(["--start", "1"]));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["march", "january"]);
+}
+
+#[test]
+fn since_after_until_is_an_error() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["list", "--since", "2024-03-01", "--until", "2024-02-01"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--since 2024-03-01 is after --until 2024-02-01"), "{stderr}");
+}
+
+#[test]
+fn dates_must_be_iso() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["list", "--since", "last week"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid --since 'last week'"));
+}
+
+#[test]
+fn locked_index_stops_a_batch() {
+    let repo = stashes_from_three_months();
+    repo.write(".git/index.lock", "");
+
+    let output = repo.run(["--apply-all"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("another git process is using this repository"), "{stderr}");
+    assert!(stderr.contains("index.lock exists"), "{stderr}");
+}
+
+#[test]
+fn number_commits_orders_a_collected_branch() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["--collect-branch", "rescued", "--number-commits"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let subjects = repo.git(["log", "-3", "--format=%s", "rescued"]);
+    assert_eq!(subjects, "[3/3] march\n[2/3] february\n[1/3] january\n");
+}
+
+#[test]
+fn number_commits_leaves_branch_names_alone() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["--branch-all", "--number-commits", "--since", "2024-02-01"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.branches(), ["main", "stash/february", "stash/march"]);
+    assert_eq!(repo.git(["log", "-1", "--format=%s", "stash/february"]), "[1/2] february\n");
+    assert_eq!(repo.git(["log", "-1", "--format=%s", "stash/march"]), "[2/2] march\n");
+}
+
+#[test]
+fn number_commits_needs_a_branching_batch() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["--drop-applied", "--number-commits"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--number-commits only works with --branch-all"), "{stderr}");
+}
+
+#[test]
+fn ages_too_long_to_count_are_an_error() {
+    let repo = stashes_from_three_months();
+
+    let output = repo.run(["list", "--older-than", "99999999999999999y"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid --older-than '99999999999999999y'"), "{stderr}");
+}
+
+/// Point `gpg.program` at a stand-in that writes its arguments to a file,
+/// which it returns, and signs with a made-up signature git takes on trust.
+#[cfg(unix)]
+fn fake_gpg(repo: &TempRepo) -> std::path::PathBuf {
+    let log = repo.path.join(".git").join("gpg-calls");
+    let program = repo.path.join(".git").join("fake-gpg");
+    std::fs::write(&program, format!(
+        "#!/bin/sh\n\
+        echo \"$@\" >> '{}'\n\
+        cat > /dev/null\n\
+        echo '[GNUPG:] SIG_CREATED D 1 8 00 0 0' >&2\n\
+        printf -- '-----BEGIN PGP SIGNATURE-----\\nfake\\n-----END PGP SIGNATURE-----\\n'\n",
+        log.display(),
+    )).expect("write fake gpg");
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755))
+        .expect("make fake gpg executable");
+    repo.git(["config", "gpg.program", program.to_str().expect("utf-8 path")]);
+    log
+}
+
+#[cfg(unix)]
+#[test]
+fn sign_reaches_git_commit_with_its_key() {
+    for (flag, key) in [("-Srescue-key", "rescue-key"), ("--sign", "")] {
+        let repo = TempRepo::new();
+        repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+        let log = fake_gpg(&repo);
+
+        let output = repo.run(["--branch-all", flag]);
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let calls = std::fs::read_to_string(&log).expect("gpg was run");
+        assert_eq!(calls.lines().count(), 1, "{calls}");
+        assert!(calls.contains(&format!("-bsau {key}")), "{flag}: {calls}");
+        let commit = repo.git(["cat-file", "commit", "stash/change_a"]);
+        assert!(commit.contains("gpgsig -----BEGIN PGP SIGNATURE-----"), "{commit}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn branch_all_says_how_to_get_back_when_switching_back_fails() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    // git gives the hook's exit status as checkout's own.
+    let hook = repo.path.join(".git").join("hooks").join("post-checkout");
+    std::fs::create_dir_all(hook.parent().expect("hooks directory")).expect("make hooks");
+    std::fs::write(&hook, "#!/bin/sh\n[ \"$(git symbolic-ref --short HEAD)\" != main ]\n")
+        .expect("write hook");
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755))
+        .expect("make hook executable");
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("run `git checkout main` to return there"), "{stderr}");
+}
+
+#[test]
+fn any_number_of_verbose_flags_is_taken() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    // Together more than a `u8` counts.
+    let flag = format!("-{}", "v".repeat(200));
+
+    let output = repo.run([flag.as_str(), flag.as_str(), "--verbose", "list"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Change a"));
+}
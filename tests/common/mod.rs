@@ -0,0 +1,292 @@
+//! A throwaway git repository for driving `git-stash-inbox` against.
+
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A fresh repository under the system temp directory with one commit on
+/// `main`, deleted again when dropped. Git runs with the repository's own
+/// config only, so the user's settings can't change the results.
+pub struct TempRepo {
+    pub path: PathBuf,
+}
+
+impl TempRepo {
+    pub fn new() -> Self {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "git-stash-inbox-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("create temp repo");
+        let repo = TempRepo { path };
+        repo.git(["init", "-q"]);
+        repo.git(["symbolic-ref", "HEAD", "refs/heads/main"]);
+        repo.git(["config", "user.name", "Test"]);
+        repo.git(["config", "user.email", "test@example.com"]);
+        repo.git(["config", "commit.gpgsign", "false"]);
+        repo.write("README", "base\n");
+        repo.git(["add", "README"]);
+        repo.git(["commit", "-q", "-m", "init"]);
+        repo
+    }
+
+    /// A command run in the repository, isolated from global git config.
+    fn command(&self, program: impl AsRef<Path>) -> Command {
+        let mut cmd = Command::new(program.as_ref());
+        cmd.current_dir(&self.path)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", self.path.join(".no-global-config"))
+            .env("XDG_CONFIG_HOME", self.path.join(".git").join("xdg-config"))
+            .env("GIT_EDITOR", "false")
+            .env("NO_COLOR", "1")
+            .stdin(Stdio::null());
+        cmd
+    }
+
+    /// Run git, panicking if it fails, and return its stdout.
+    pub fn git<'a>(&self, args: impl IntoIterator<Item = &'a str>) -> String {
+        let args: Vec<&str> = args.into_iter().collect();
+        let output = self.command("git").args(&args).output().expect("run git");
+        assert!(
+            output.status.success(),
+            "git {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr),
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    pub fn write(&self, file: &str, contents: &str) {
+        std::fs::write(self.path.join(file), contents).expect("write file");
+    }
+
+    pub fn read(&self, file: &str) -> String {
+        std::fs::read_to_string(self.path.join(file)).expect("read file")
+    }
+
+    /// Track `file` with `base` committed, then stash a change to `changed`.
+    pub fn stash(&self, file: &str, base: &str, changed: &str, message: &str) {
+        self.change(file, base, changed);
+        self.git(["stash", "push", "-q", "-m", message]);
+    }
+
+    /// Like `stash`, but made at `date`, in any format git accepts.
+    pub fn stash_at(&self, date: &str, file: &str, base: &str, changed: &str, message: &str) {
+        self.change(file, base, changed);
+        let status = self.command("git")
+            .args(["stash", "push", "-q", "-m", message])
+            .env("GIT_COMMITTER_DATE", date)
+            .env("GIT_AUTHOR_DATE", date)
+            .status()
+            .expect("run git stash");
+        assert!(status.success(), "git stash push failed");
+    }
+
+    /// Track `file` with `base` committed if it isn't already, then change it
+    /// to `changed`.
+    fn change(&self, file: &str, base: &str, changed: &str) {
+        if !self.path.join(file).exists() {
+            self.write(file, base);
+            self.git(["add", file]);
+            self.git(["commit", "-q", "-m", &format!("add {file}")]);
+        }
+        self.write(file, changed);
+    }
+
+    /// Write the user's `config.toml`, where `XDG_CONFIG_HOME` points for
+    /// commands run in this repository.
+    pub fn write_user_config(&self, contents: &str) {
+        let dir = self.path.join(".git").join("xdg-config").join("git-stash-inbox");
+        std::fs::create_dir_all(&dir).expect("create config dir");
+        std::fs::write(dir.join("config.toml"), contents).expect("write config.toml");
+    }
+
+    /// Stash messages, newest first, without their "On main: " prefix.
+    pub fn stashes(&self) -> Vec<String> {
+        self.git(["stash", "list", "--format=%gs"])
+            .lines()
+            .map(|line| line.split_once(": ").map_or(line, |(_, s)| s).to_string())
+            .collect()
+    }
+
+    pub fn branches(&self) -> Vec<String> {
+        self.git(["for-each-ref", "--format=%(refname:short)", "refs/heads"])
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Write a `--script` file where git won't see it as a local change.
+    pub fn script(&self, lines: &[&str]) -> String {
+        let path = self.path.join(".git").join("inbox-script");
+        std::fs::write(&path, lines.join("\n")).expect("write script");
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Run `git-stash-inbox` with `args`, with no input.
+    pub fn run<'a>(&self, args: impl IntoIterator<Item = &'a str>) -> Output {
+        self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .args(args)
+            .output()
+            .expect("run git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args`, feeding it `input` on stdin.
+    pub fn run_with_input<'a>(
+        &self,
+        input: &str,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Output {
+        let mut child = self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run git-stash-inbox");
+        let mut stdin = child.stdin.take().expect("stdin");
+        stdin.write_all(input.as_bytes()).expect("write input");
+        drop(stdin);
+        child.wait_with_output().expect("wait for git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args`, typing `lines` on stdin with a wait
+    /// of `pause` between each.
+    pub fn run_with_paced_input<'a>(
+        &self,
+        pause: std::time::Duration,
+        lines: &[&str],
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Output {
+        let mut child = self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run git-stash-inbox");
+        let mut stdin = child.stdin.take().expect("stdin");
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                std::thread::sleep(pause);
+            }
+            // It may have exited already, which the output will show.
+            let _ = writeln!(stdin, "{line}");
+        }
+        drop(stdin);
+        child.wait_with_output().expect("wait for git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args` on a terminal of 20 rows by 80
+    /// columns, made by util-linux `script`, typing `keys` once it has had a
+    /// second to start, or `None` if there is no `script` to run.
+    pub fn run_in_terminal<'a>(
+        &self,
+        keys: &str,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Option<Output> {
+        let program = env!("CARGO_BIN_EXE_git-stash-inbox");
+        let mut command = format!("stty rows 20 cols 80; '{program}'");
+        for arg in args {
+            command.push_str(&format!(" '{arg}'"));
+        }
+        let mut child = self.command("script")
+            .args(["-qec", &command, "/dev/null"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .ok()?;
+        // Held open until it exits, since `script` stops at end of input.
+        let mut stdin = child.stdin.take().expect("stdin");
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        let _ = stdin.write_all(keys.as_bytes());
+        Some(child.wait_with_output().expect("wait for script"))
+    }
+
+    /// Run `git-stash-inbox` with `args` and a stdin that stays open but
+    /// never sends anything, as when nobody is there to answer.
+    pub fn run_unanswered<'a>(&self, args: impl IntoIterator<Item = &'a str>) -> Output {
+        let mut child = self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run git-stash-inbox");
+        // Held until the process exits, so it never sees end of input.
+        let _stdin = child.stdin.take();
+        child.wait_with_output().expect("wait for git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args` from the subdirectory `dir`.
+    pub fn run_in<'a>(&self, dir: &str, args: impl IntoIterator<Item = &'a str>) -> Output {
+        self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .current_dir(self.path.join(dir))
+            .args(args)
+            .output()
+            .expect("run git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args` and the environment variable `var`
+    /// set to `value`.
+    pub fn run_with_env<'a>(
+        &self,
+        var: &str,
+        value: &str,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Output {
+        self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .env(var, value)
+            .args(args)
+            .output()
+            .expect("run git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args` and only `dir`, then wherever git
+    /// itself is, on `PATH`, so that programs it looks for can be faked.
+    pub fn run_with_path<'a>(
+        &self,
+        dir: &Path,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Output {
+        let git = self.command("sh").args(["-c", "command -v git"]).output().expect("find git");
+        let git = PathBuf::from(String::from_utf8_lossy(&git.stdout).trim());
+        let path = std::env::join_paths([dir, git.parent().expect("git's directory")])
+            .expect("join PATH");
+        self.command(env!("CARGO_BIN_EXE_git-stash-inbox"))
+            .env("PATH", path)
+            .env_remove("WAYLAND_DISPLAY")
+            .args(args)
+            .output()
+            .expect("run git-stash-inbox")
+    }
+
+    /// Run `git-stash-inbox` with `args` and `editor` as `GIT_EDITOR`, in a
+    /// process group of its own, so an editor can signal the group the way
+    /// Ctrl-C at a terminal does without reaching the tests.
+    pub fn run_with_editor<'a>(
+        &self,
+        editor: &str,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Output {
+        let mut cmd = self.command(env!("CARGO_BIN_EXE_git-stash-inbox"));
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        cmd.env("GIT_EDITOR", editor)
+            .args(args)
+            .output()
+            .expect("run git-stash-inbox")
+    }
+}
+
+impl Drop for TempRepo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
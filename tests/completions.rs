@@ -0,0 +1,86 @@
+//! The hidden `completions` subcommand.
+
+mod common;
+
+use std::process::{Command, Stdio};
+
+use common::TempRepo;
+
+fn completions(repo: &TempRepo, shell: &str) -> String {
+    let output = repo.run(["completions", shell]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Whether `shell -n` accepts `script`, or `None` if the shell isn't installed.
+fn parses(repo: &TempRepo, shell: &str, script: &str) -> Option<bool> {
+    let file = repo.path.join(format!("completions.{shell}"));
+    std::fs::write(&file, script).expect("write completion script");
+    let status = Command::new(shell)
+        .arg("-n")
+        .arg(&file)
+        .stdout(Stdio::null())
+        .status()
+        .ok()?;
+    Some(status.success())
+}
+
+#[test]
+fn bash_completion_parses_and_offers_the_flags() {
+    let repo = TempRepo::new();
+    let script = completions(&repo, "bash");
+
+    assert_eq!(parses(&repo, "bash", &script), Some(true), "{script}");
+    for word in ["--drop-applied", "--grep", "--range", "list", "import"] {
+        assert!(script.contains(word), "{word} missing from:\n{script}");
+    }
+}
+
+#[test]
+fn zsh_and_fish_completions_parse_where_installed() {
+    let repo = TempRepo::new();
+    for shell in ["zsh", "fish"] {
+        let script = completions(&repo, shell);
+        assert!(script.contains("drop-applied"), "{script}");
+        if let Some(ok) = parses(&repo, shell, &script) {
+            assert!(ok, "{shell} rejected:\n{script}");
+        }
+    }
+}
+
+#[test]
+fn every_completed_switch_is_accepted() {
+    let repo = TempRepo::new();
+    let script = completions(&repo, "bash");
+    // Flags that take a value have a case of their own in the script.
+    let switches = script
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("COMPREPLY=($(compgen -W \""))
+        .and_then(|words| words.split('"').next())
+        .expect("word list")
+        .split(' ')
+        .filter(|word| word.starts_with('-'))
+        .filter(|word| {
+            [format!(" {word})"), format!(" {word}|"), format!("|{word})")]
+                .iter()
+                .all(|case| !script.contains(case.as_str()))
+        });
+
+    for switch in switches {
+        let output = repo.run([switch, "list"]);
+        assert!(
+            !String::from_utf8_lossy(&output.stderr).contains("unknown argument"),
+            "{switch} isn't accepted",
+        );
+    }
+}
+
+#[test]
+fn unknown_shells_are_an_error() {
+    let repo = TempRepo::new();
+
+    let output = repo.run(["completions", "tcsh"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unsupported shell 'tcsh'"));
+}
@@ -0,0 +1,72 @@
+//! Settings from the user's `config.toml`, and how they rank against git
+//! config and flags.
+
+mod common;
+
+use common::TempRepo;
+
+fn one_stash() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo
+}
+
+#[test]
+fn config_file_sets_the_branch_prefix() {
+    let repo = one_stash();
+    repo.write_user_config("# rescued stashes\nbranch-prefix = \"rescued/\"\n");
+
+    let output = repo.run(["--branch-all"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.branches(), ["main", "rescued/change_a"]);
+}
+
+#[test]
+fn git_config_beats_the_config_file_and_flags_beat_both() {
+    let repo = one_stash();
+    repo.write_user_config("branch-prefix = 'rescued/'\n");
+    repo.git(["config", "stashinbox.branchPrefix", "git/"]);
+
+    let output = repo.run(["--branch-all", "--dry-run"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("git/change_a"));
+
+    let output = repo.run(["--branch-all", "--dry-run", "--branch-prefix", "flag/"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("flag/change_a"));
+}
+
+#[test]
+fn config_file_binds_keys() {
+    let repo = one_stash();
+    repo.write_user_config("[keys]\ndrop = \"x\"\n");
+    let script = repo.script(&["x", "y"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(repo.stashes().is_empty());
+}
+
+#[test]
+fn malformed_config_file_names_the_line() {
+    let repo = one_stash();
+    repo.write_user_config("color = \"auto\"\n\ndefault-action = drop\n");
+
+    let output = repo.run(["list"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("config.toml:3: invalid value 'drop'"), "{stderr}");
+}
+
+#[test]
+fn unknown_settings_are_an_error() {
+    let repo = one_stash();
+    repo.write_user_config("[keys]\ndorp = \"x\"\n");
+
+    let output = repo.run(["list"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("config.toml:2: unknown action 'dorp' in [keys]"), "{stderr}");
+}
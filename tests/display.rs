@@ -0,0 +1,146 @@
+//! What the loop shows for each stash.
+
+mod common;
+
+use common::TempRepo;
+
+fn shown(repo: &TempRepo, args: &[&str]) -> String {
+    let script = repo.script(&["q"]);
+    let output = repo.run(args.iter().copied().chain(["--script", &script]));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn color_always_colors_the_diff_despite_git_config() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "change a");
+    repo.git(["config", "color.ui", "false"]);
+
+    let stdout = shown(&repo, &["--color=always"]);
+
+    assert!(stdout.contains("\x1b[31m-a\x1b[m"), "{stdout:?}");
+}
+
+#[test]
+fn color_never_leaves_the_diff_plain_despite_git_config() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "change a");
+    repo.git(["config", "color.ui", "always"]);
+
+    let stdout = shown(&repo, &["--color=never"]);
+
+    assert!(stdout.contains("\n+a changed\n"), "{stdout:?}");
+    assert!(!stdout.contains('\x1b'), "{stdout:?}");
+}
+
+fn binary_stash() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.write("image.bin", "\0old");
+    repo.git(["add", "image.bin"]);
+    repo.git(["commit", "-q", "-m", "add image"]);
+    repo.write("image.bin", "\0new");
+    repo.write("README", "base\nmore\n");
+    repo.git(["stash", "-q"]);
+    repo
+}
+
+#[test]
+fn binary_files_are_summarized_after_the_patch() {
+    let repo = binary_stash();
+
+    let stdout = shown(&repo, &[]);
+
+    assert!(stdout.contains("+more\n1 binary file changed: image.bin\n"), "{stdout:?}");
+    assert!(!stdout.contains("Binary files"), "{stdout:?}");
+}
+
+#[test]
+fn no_binary_summary_shows_the_raw_patch() {
+    let repo = binary_stash();
+
+    let stdout = shown(&repo, &["--no-binary-summary"]);
+
+    assert!(stdout.contains("Binary files a/image.bin and b/image.bin differ"), "{stdout:?}");
+    assert!(!stdout.contains("binary file changed"), "{stdout:?}");
+}
+
+fn renaming_stash() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.write("old_name.txt", &"line\n".repeat(20));
+    repo.git(["add", "old_name.txt"]);
+    repo.git(["commit", "-q", "-m", "add old_name.txt"]);
+    repo.git(["mv", "old_name.txt", "new.txt"]);
+    repo.write("README", "base\nmore\n");
+    repo.write("added.txt", "added\n");
+    repo.git(["add", "-A"]);
+    repo.git(["stash", "-q"]);
+    repo
+}
+
+#[test]
+fn names_line_up_statuses_and_renames() {
+    let repo = renaming_stash();
+
+    let stdout = shown(&repo, &["--names"]);
+
+    assert!(stdout.contains("M     README\n"), "{stdout:?}");
+    assert!(stdout.contains("A     added.txt\n"), "{stdout:?}");
+    assert!(stdout.contains("R100  old_name.txt -> new.txt\n"), "{stdout:?}");
+}
+
+#[test]
+fn names_color_renames_blue() {
+    let repo = renaming_stash();
+
+    let stdout = shown(&repo, &["--names", "--color=always"]);
+
+    assert!(stdout.contains("\x1b[34mR100\x1b[0m  old_name.txt"), "{stdout:?}");
+    assert!(stdout.contains("\x1b[33mM\x1b[0m     README"), "{stdout:?}");
+}
+
+#[test]
+fn overview_lists_every_stash_and_marks_the_start() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "change b");
+
+    let stdout = shown(&repo, &["--overview", "--start", "1"]);
+
+    assert!(stdout.contains(" 1. stash@{0} 1 file, "), "{stdout:?}");
+    assert!(stdout.contains(">2. stash@{1} 1 file, "), "{stdout:?}");
+    assert!(stdout.contains("ago: change a\n\n"), "{stdout:?}");
+}
+
+#[test]
+fn help_lists_the_prompted_actions_with_their_configured_keys() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "change a");
+    repo.git(["config", "stashinbox.key.drop", "x"]);
+    let script = repo.script(&["?", "q"]);
+
+    let output = repo.run(["--script", &script]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[x,D,"), "{stdout:?}");
+    assert!(stdout.contains("\nx - drop this stash\n"), "{stdout:?}");
+    // Nothing was dropped yet, so undo is neither prompted nor listed.
+    assert!(!stdout.contains("u - undo"), "{stdout:?}");
+}
+
+#[test]
+fn file_action_picks_from_the_changed_files() {
+    let repo = renaming_stash();
+    let script = repo.script(&["f", "1", "c", "f", "3", "d", "q"]);
+
+    let output = repo.run(["--no-shortstat", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1) M     README\n"), "{stdout}");
+    assert!(stdout.contains("3) R100  old_name.txt -> new.txt\n"), "{stdout}");
+    let (_, content) = stdout.split_once("or its [d]iff? c\n").expect("content");
+    assert!(content.starts_with("base\nmore\n"), "{content}");
+    let (_, diff) = content.split_once("[d]iff? d\n").expect("diff");
+    assert!(diff.starts_with("diff --git a/old_name.txt b/new.txt\n"), "{diff}");
+}
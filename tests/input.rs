@@ -0,0 +1,83 @@
+//! Responses read from a piped stdin rather than the terminal.
+
+mod common;
+
+use common::TempRepo;
+
+fn two_stashes() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "older");
+    repo.stash("b.txt", "b\n", "b changed\n", "newer");
+    repo
+}
+
+#[test]
+fn crlf_line_endings_are_ignored() {
+    let repo = two_stashes();
+
+    // Drop the newer stash, answering "y" to the "may not be applied" prompt.
+    let output = repo.run_with_input("d\r\ny\r\n", []);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["older"]);
+}
+
+#[test]
+fn trailing_carriage_return_without_newline_is_ignored() {
+    let repo = two_stashes();
+
+    let output = repo.run_with_input("r\r\nrenamed\r", []);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["renamed", "older"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn timeout_takes_the_default_without_asking() {
+    let repo = two_stashes();
+    repo.git(["stash", "apply", "-q", "stash@{1}"]);
+
+    let output = repo.run_unanswered(["--timeout", "1", "--default-action", "drop"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("No response after 1s.").count(), 2, "{stdout:?}");
+    // The newer stash isn't applied, so it's skipped rather than dropped.
+    assert!(stdout.contains("Skipped it."), "{stdout:?}");
+    assert_eq!(repo.stashes(), ["newer"]);
+}
+
+#[test]
+fn a_timed_out_question_doesnt_swallow_the_next_answer() {
+    let repo = two_stashes();
+
+    // The drop confirmation times out, then `q` arrives at the action prompt.
+    let output = repo.run_with_paced_input(
+        std::time::Duration::from_millis(1500),
+        &["d", "q"],
+        ["--timeout", "1", "--line-input"],
+    );
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("No response after 1s.").count(), 1, "{stdout:?}");
+    assert!(!stdout.contains("Skipped it."), "{stdout:?}");
+    assert!(!stdout.contains("Stash 2 of 2"), "{stdout:?}");
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn locked_index_asks_to_retry() {
+    let repo = two_stashes();
+    repo.write(".git/index.lock", "");
+
+    // Apply, retry once while still locked, then give up.
+    let output = repo.run_with_input("a\n\nn\n", []);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let warnings = stderr.matches("Another git process is using this repository").count();
+    assert_eq!(warnings, 2, "{stderr}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).matches("Retry once").count(), 2);
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
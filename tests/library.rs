@@ -0,0 +1,95 @@
+//! The library API, which runs git in the current directory.
+
+mod common;
+
+use std::sync::Mutex;
+
+use common::TempRepo;
+use git_stash_inbox::{drop_stash, list_stashes, rescue_to_branch, stash_is_applied};
+
+/// Changing directory affects every test thread, so only one test may be
+/// inside a repository at a time.
+static CWD: Mutex<()> = Mutex::new(());
+
+fn in_repo(test: impl FnOnce(&TempRepo)) {
+    let _lock = CWD.lock().unwrap_or_else(|e| e.into_inner());
+    let repo = TempRepo::new();
+    // The same isolation `TempRepo` gives the commands it runs itself.
+    std::env::set_var("GIT_CONFIG_NOSYSTEM", "1");
+    std::env::set_var("GIT_CONFIG_GLOBAL", repo.path.join(".no-global-config"));
+    std::env::set_current_dir(&repo.path).expect("enter temp repo");
+    test(&repo);
+    std::env::set_current_dir(std::env::temp_dir()).expect("leave temp repo");
+}
+
+#[test]
+fn lists_stashes_newest_first() {
+    in_repo(|repo| {
+        repo.stash("a.txt", "a\n", "a changed\n", "first");
+        repo.stash("a.txt", "a\n", "a changed again\n", "second");
+
+        let stashes = list_stashes(None).unwrap();
+
+        let subjects: Vec<&str> = stashes.iter().map(|s| s.subject()).collect();
+        assert_eq!(subjects, ["second", "first"]);
+        assert_eq!(stashes[1].index, 1);
+        assert_eq!(stashes[0].branch, "main");
+        assert_eq!(list_stashes(Some("^On main: f")).unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn detects_applied_stashes() {
+    in_repo(|repo| {
+        repo.stash("a.txt", "a\n", "a changed\n", "change a");
+        let stash = &list_stashes(None).unwrap()[0];
+        assert!(!stash_is_applied(stash).unwrap());
+
+        repo.git(["stash", "apply", "-q"]);
+
+        assert!(stash_is_applied(stash).unwrap());
+    });
+}
+
+#[test]
+fn untracked_files_must_be_back_for_a_stash_to_be_applied() {
+    in_repo(|repo| {
+        repo.write("new.txt", "new\n");
+        repo.git(["stash", "push", "-q", "-u", "-m", "untracked only"]);
+        let stash = &list_stashes(None).unwrap()[0];
+        assert!(!stash_is_applied(stash).unwrap());
+
+        repo.write("new.txt", "different\n");
+        assert!(!stash_is_applied(stash).unwrap());
+
+        repo.write("new.txt", "new\n");
+        assert!(stash_is_applied(stash).unwrap());
+    });
+}
+
+#[test]
+fn drops_a_stash() {
+    in_repo(|repo| {
+        repo.stash("a.txt", "a\n", "a changed\n", "first");
+        repo.stash("a.txt", "a\n", "a changed again\n", "second");
+        let stashes = list_stashes(None).unwrap();
+
+        assert!(drop_stash(&stashes[1]).unwrap());
+
+        assert_eq!(repo.stashes(), ["second"]);
+    });
+}
+
+#[test]
+fn rescues_a_stash_to_a_branch() {
+    in_repo(|repo| {
+        repo.stash("a.txt", "a\n", "a changed\n", "change a");
+        let stash = &list_stashes(None).unwrap()[0];
+
+        let branch = rescue_to_branch(stash, "Rescue: the a change").unwrap();
+
+        assert_eq!(branch.as_deref(), Some("stash/rescue_the_a_change"));
+        assert!(repo.stashes().is_empty());
+        assert_eq!(repo.git(["show", "stash/rescue_the_a_change:a.txt"]), "a changed\n");
+    });
+}
@@ -0,0 +1,643 @@
+//! The interactive loop, driven by `--script`.
+
+mod common;
+
+use common::TempRepo;
+
+fn two_stashes() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "older");
+    repo.stash("b.txt", "b\n", "b changed\n", "newer");
+    repo
+}
+
+#[test]
+fn script_lines_drive_actions_and_prompts() {
+    let repo = two_stashes();
+    // Skip the newer stash, then drop the older one, answering the "may not
+    // be applied" prompt.
+    let script = repo.script(&["s", "d", "y"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["newer"]);
+}
+
+#[test]
+fn script_running_out_ends_the_session() {
+    let repo = two_stashes();
+    let script = repo.script(&["s"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success());
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn unknown_script_action_is_an_error() {
+    let repo = two_stashes();
+    let script = repo.script(&["s", "xyz", "d"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("script line 2: unknown action 'xyz'"), "{stderr}");
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn missing_script_is_an_error() {
+    let repo = two_stashes();
+
+    let output = repo.run(["--script", "does-not-exist"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't read script"));
+}
+
+#[test]
+fn difftool_opens_the_configured_tool() {
+    let repo = two_stashes();
+    repo.git(["config", "diff.tool", "show"]);
+    repo.git(["config", "difftool.show.cmd", "echo tool: && cat \"$REMOTE\""]);
+    let script = repo.script(&["v", "q"]);
+
+    let output = repo.run(["--difftool", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("tool:\nb changed\n"));
+}
+
+#[test]
+fn difftool_without_a_tool_shows_the_diff() {
+    let repo = two_stashes();
+    let script = repo.script(&["v", "q"]);
+
+    let output = repo.run(["--difftool", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No diff.tool configured"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, after_view) = stdout.split_once("? v").expect("view prompt");
+    assert!(after_view.contains("+b changed"));
+}
+
+#[test]
+fn open_shows_the_patch_in_the_editor_and_ignores_edits() {
+    let repo = two_stashes();
+    let script = repo.script(&["o", "q"]);
+
+    let output = repo.run_with_editor(
+        "sed -n 's/^+b/editor: &/p' \"$1\" && echo edited >>",
+        ["--script", &script],
+    );
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("editor: +b changed"));
+    assert_eq!(repo.git(["stash", "show", "-p", "stash@{0}"]).matches("edited").count(), 0);
+}
+
+#[test]
+fn info_shows_the_reflog_entry_and_base_commit() {
+    let repo = two_stashes();
+    let script = repo.script(&["i", "q"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Reflog: stash@{0}"), "{stdout}");
+    assert!(stdout.contains("Made on top of:"), "{stdout}");
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn showing_a_stash_again_reuses_what_git_already_said() {
+    let repo = two_stashes();
+    // Show the newer stash, view it, step past and back, then drop it.
+    let script = repo.script(&["v", "s", "k", "d", "y"]);
+
+    let output = repo.run(["-v", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // One applied check and one size per stash, however often it's shown.
+    assert_eq!(stderr.matches("+ git apply --reverse --check").count(), 2, "{stderr}");
+    assert_eq!(stderr.matches("stash show --shortstat").count(), 2, "{stderr}");
+    assert_eq!(repo.stashes(), ["older"]);
+}
+
+#[test]
+fn commit_commits_onto_the_current_branch_and_drops() {
+    let repo = two_stashes();
+    let script = repo.script(&["c"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.git(["log", "-1", "--format=%s"]).trim(), "newer");
+    assert_eq!(repo.git(["rev-parse", "--abbrev-ref", "HEAD"]).trim(), "main");
+    assert_eq!(repo.read("b.txt"), "b changed\n");
+    assert_eq!(repo.stashes(), ["older"]);
+}
+
+#[test]
+fn commit_refuses_with_local_changes() {
+    let repo = two_stashes();
+    repo.write("a.txt", "local\n");
+    let head = repo.git(["rev-parse", "HEAD"]);
+    let script = repo.script(&["c", "q"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("with local changes"));
+    assert_eq!(repo.git(["rev-parse", "HEAD"]), head);
+    assert_eq!(repo.read("a.txt"), "local\n");
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn start_past_the_end_is_an_error() {
+    let repo = two_stashes();
+
+    let output = repo.run(["--start", "9"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "--start: stash index 9 out of range; only 2 stashes exist"
+    ));
+}
+
+/// A directory of clipboard tools that all write the clipboard to `clipboard`.
+#[cfg(unix)]
+fn fake_clipboard(repo: &TempRepo) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bin = repo.path.join(".git").join("fake-bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let clipboard = repo.path.join(".git").join("clipboard");
+    for tool in ["pbcopy", "xclip", "xsel"] {
+        let path = bin.join(tool);
+        std::fs::write(&path, format!("#!/bin/sh\ncat > '{}'\n", clipboard.display())).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    bin
+}
+
+#[cfg(unix)]
+#[test]
+fn yank_copies_the_patch_to_the_clipboard() {
+    let repo = two_stashes();
+    let bin = fake_clipboard(&repo);
+    let script = repo.script(&["y", "q"]);
+
+    let output = repo.run_with_path(&bin, ["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("to the clipboard"));
+    let clipboard = std::fs::read_to_string(repo.path.join(".git").join("clipboard")).unwrap();
+    assert!(clipboard.contains("+b changed"), "{clipboard}");
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn yank_without_a_clipboard_tool_says_so() {
+    let repo = two_stashes();
+    let empty = repo.path.join(".git").join("empty-bin");
+    std::fs::create_dir_all(&empty).unwrap();
+    let script = repo.script(&["y", "q"]);
+
+    let output = repo.run_with_path(&empty, ["--script", &script]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No clipboard tool found"));
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn stash_ref_without_its_reflog_means_no_stashes() {
+    let repo = two_stashes();
+    // Regression: with `refs/stash` still there but its reflog gone, this
+    // used to find stashes to review and then none matching.
+    std::fs::remove_file(repo.path.join(".git").join("logs").join("refs").join("stash")).unwrap();
+
+    let output = repo.run(["--script", &repo.script(&[])]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "No stashes found.\n");
+}
+
+#[test]
+fn tui_needs_a_terminal() {
+    let repo = two_stashes();
+
+    let output = repo.run(["--tui"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--tui needs a terminal") || stderr.contains("only available on Unix"));
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn tui_shows_the_diff_within_the_paths_given() {
+    let repo = two_stashes();
+
+    let Some(output) = repo.run_in_terminal("q", ["--tui", "--", "b.txt"]) else {
+        return;
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("+b changed"), "{stdout}");
+    assert!(!stdout.contains("failed to show"), "{stdout}");
+}
+
+#[test]
+fn tui_refuses_a_script() {
+    let repo = two_stashes();
+    let script = repo.script(&["d"]);
+
+    let output = repo.run(["--tui", "--script", &script]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't be combined with --script"));
+}
+
+#[test]
+fn force_drops_without_asking() {
+    let repo = two_stashes();
+    let script = repo.script(&["d"]);
+
+    let output = repo.run(["--force", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("may not be applied"));
+    assert_eq!(repo.stashes(), ["older"]);
+}
+
+#[test]
+fn apply_onto_switches_branch_and_applies() {
+    let repo = two_stashes();
+    repo.git(["branch", "other"]);
+    let script = repo.script(&["A", "other"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.git(["symbolic-ref", "--short", "HEAD"]), "other\n");
+    assert_eq!(repo.read("b.txt"), "b changed\n");
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn apply_onto_refuses_with_local_changes() {
+    let repo = two_stashes();
+    repo.git(["branch", "other"]);
+    repo.write("README", "local edit\n");
+    // No branch is asked for, so the next line is the next action.
+    let script = repo.script(&["A", "q"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Can't switch branches"));
+    assert_eq!(repo.git(["symbolic-ref", "--short", "HEAD"]), "main\n");
+    assert_eq!(repo.read("b.txt"), "b\n");
+}
+
+#[test]
+fn select_marks_stashes_then_acts_on_them_together() {
+    let repo = two_stashes();
+    repo.stash("c.txt", "c\n", "c changed\n", "newest");
+    // Mark the newest and oldest stashes, then drop both and confirm.
+    let script = repo.script(&["x", "s", "x", "d", "y"]);
+
+    let output = repo.run(["--select", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[x] 1. stash@{0}"), "{stdout:?}");
+    assert!(stdout.contains("[ ] 2. stash@{1}"), "{stdout:?}");
+    assert_eq!(repo.stashes(), ["newer"]);
+}
+
+#[test]
+fn select_takes_a_line_of_spaces_as_the_space_key() {
+    let repo = two_stashes();
+    let script = repo.script(&[" ", "  ", "d", "y"]);
+
+    let output = repo.run(["--select", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("No stashes marked."));
+    assert!(repo.stashes().is_empty());
+}
+
+#[test]
+fn select_does_nothing_unless_confirmed() {
+    let repo = two_stashes();
+    let script = repo.script(&["x", "x", "d", "n"]);
+
+    let output = repo.run(["--select", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn porcelain_prints_a_line_per_action() {
+    let repo = two_stashes();
+    let newer = repo.git(["rev-parse", "stash@{0}"]);
+    let older = repo.git(["rev-parse", "stash@{1}"]);
+    let script = repo.script(&["s", "d", "y"]);
+
+    let output = repo.run(["--porcelain", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let events: Vec<&str> = stdout.lines().collect();
+    assert_eq!(events, [
+        "# porcelain v1".to_string(),
+        format!("SKIPPED stash@{{0}} {}", newer.trim()),
+        format!("DROPPED stash@{{1}} {}", older.trim()),
+    ]);
+    // The usual human output, git's included, goes to stderr instead.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Stash 1 of 2"), "{stderr}");
+    assert!(stderr.contains("+b changed"), "{stderr}");
+    assert!(stderr.contains("Dropped stash@{1}"), "{stderr}");
+}
+
+#[test]
+fn porcelain_is_never_colored() {
+    let repo = two_stashes();
+
+    let output = repo.run(["--porcelain", "--color=always", "list"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--porcelain output is never colored"), "{stderr}");
+}
+
+#[test]
+fn unreadable_stash_is_reported_and_can_be_skipped() {
+    let repo = two_stashes();
+    let blob = repo.git(["rev-parse", "stash@{0}:b.txt"]);
+    let blob = blob.trim();
+    std::fs::remove_file(repo.path.join(".git/objects").join(&blob[..2]).join(&blob[2..]))
+        .expect("remove blob");
+    let script = repo.script(&["s"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // git's own complaint gets through, followed by which stash it was.
+    assert!(stderr.contains("unable to read"), "{stderr}");
+    assert!(stderr.contains("error: failed to diff stash@{0}"), "{stderr}");
+    // The session carries on to the next stash instead of ending there.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Stash 2 of 2"), "{stdout}");
+    assert!(stdout.contains("+a changed"), "{stdout}");
+}
+
+/// Stash a staged change to the first line of `a.txt`.
+fn staged_stash() -> TempRepo {
+    let repo = TempRepo::new();
+    repo.write("a.txt", "1\n2\n3\n4\n5\n");
+    repo.git(["add", "a.txt"]);
+    repo.git(["commit", "-q", "-m", "add a.txt"]);
+    repo.write("a.txt", "1 staged\n2\n3\n4\n5\n");
+    repo.git(["add", "a.txt"]);
+    repo.git(["stash", "push", "-q", "-m", "Stage a"]);
+    repo
+}
+
+#[test]
+fn pop_index_restages_what_was_staged() {
+    let repo = staged_stash();
+    let script = repo.script(&["P"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(repo.stashes().is_empty());
+    assert_eq!(repo.git(["diff", "--cached", "--name-only"]), "a.txt\n");
+}
+
+#[test]
+fn pop_index_warns_and_pops_unstaged_when_the_index_cant_be_restored() {
+    let repo = staged_stash();
+    // A commit within the patch's context stops it applying to the index,
+    // though the lines are far enough apart to merge.
+    repo.write("a.txt", "1\n2\n3 committed\n4\n5\n");
+    repo.git(["commit", "-q", "-am", "change a"]);
+    let script = repo.script(&["P"]);
+
+    let output = repo.run(["--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Couldn't restore the index of stash@{0}"), "{stderr}");
+    assert!(repo.stashes().is_empty());
+    assert_eq!(repo.read("a.txt"), "1 staged\n2\n3 committed\n4\n5\n");
+    assert_eq!(repo.git(["diff", "--cached", "--name-only"]), "");
+}
+
+#[test]
+fn log_accumulates_each_sessions_actions() {
+    let repo = two_stashes();
+    let log = repo.path.join("stashes.log");
+    let log_arg = log.to_str().expect("utf-8 path");
+    let script = repo.script(&["s", "d", "y"]);
+
+    let output = repo.run(["--script", &script, "--log", log_arg]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = repo.run(["--drop-applied", "--log", log_arg]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let text = std::fs::read_to_string(&log).expect("read log");
+    let events: Vec<&str> = text.lines()
+        .map(|line| line.split_once(' ').expect("timestamp").1)
+        .map(|line| line.split(' ').next().expect("event"))
+        .collect();
+    assert_eq!(events, ["START", "SKIPPED", "DROPPED", "START"], "{text}");
+    assert!(text.lines().all(|line| line.as_bytes()[10] == b'T'), "{text}");
+    assert!(text.contains("\tOn main: older\n"), "{text}");
+}
+
+#[test]
+fn log_that_cant_be_opened_is_an_error_up_front() {
+    let repo = two_stashes();
+
+    let output = repo.run(["--log", "missing/dir/stashes.log"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("can't open log file missing/dir/stashes.log"), "{stderr}");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Stash 1 of 2"));
+    assert_eq!(repo.stashes().len(), 2);
+}
+
+#[test]
+fn actions_after_a_drop_reach_the_renumbered_stash() {
+    let repo = TempRepo::new();
+    for (file, message) in [("a.txt", "fix a"), ("b.txt", "keep b"), ("c.txt", "fix c")] {
+        repo.stash(file, "base\n", "changed\n", message);
+    }
+    repo.stash("d.txt", "base\n", "changed\n", "keep d");
+    // Only the fixes are listed, so dropping `fix c` moves `fix a` from
+    // stash@{3} to stash@{2} without the list being read again.
+    let script = repo.script(&["d", "d"]);
+
+    let output = repo.run(["--force", "--grep", "fix", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(repo.stashes(), ["keep d", "keep b"]);
+}
+
+#[test]
+fn edit_branch_name_asks_until_the_name_will_do() {
+    let repo = two_stashes();
+    let script = repo.script(&["b", "bad..name", "main", "rescued", "b", ""]);
+
+    let output = repo.run_with_editor(
+        "echo 'Rescue it' >",
+        ["--edit-branch-name", "--script", &script],
+    );
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Branch name [stash/rescue_it]: "), "{stdout}");
+    assert!(stdout.contains("'bad..name' isn't a valid branch name"), "{stdout}");
+    assert!(stdout.contains("A branch named main already exists"), "{stdout}");
+    // An empty answer keeps the proposed name.
+    assert_eq!(repo.branches(), ["main", "rescued", "stash/rescue_it"]);
+    assert!(repo.stashes().is_empty());
+}
+
+#[test]
+fn edit_branch_name_is_refused_in_a_batch() {
+    let repo = two_stashes();
+
+    let output = repo.run(["--edit-branch-name", "--branch-all"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--edit-branch-name asks for each name"), "{stderr}");
+    assert_eq!(repo.stashes().len(), 2);
+}
+
+/// An editor that is sent Ctrl-C, as the whole terminal's process group is,
+/// and gives up.
+#[cfg(unix)]
+const INTERRUPTED_EDITOR: &str = "kill -INT 0; exit 1; :";
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_in_the_commit_editor_leaves_the_session_running() {
+    let repo = two_stashes();
+    let script = repo.script(&["b", "q"]);
+
+    let output = repo.run_with_editor(INTERRUPTED_EDITOR, ["--script", &script]);
+
+    assert_ne!(output.status.code(), Some(130), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Stash 1 of 2"));
+    assert_eq!(repo.git(["branch", "--show-current"]), "main\n");
+    assert_eq!(repo.branches(), ["main"]);
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_in_the_editor_still_deletes_the_opened_patch() {
+    let repo = two_stashes();
+    let script = repo.script(&["o", "q"]);
+    let marker = repo.path.join("opened");
+    let editor = format!("echo \"$1\" > {}; {INTERRUPTED_EDITOR}", marker.display());
+
+    let output = repo.run_with_editor(&editor, ["--script", &script]);
+
+    assert_ne!(output.status.code(), Some(130), "{}", String::from_utf8_lossy(&output.stderr));
+    let opened = std::fs::read_to_string(&marker).expect("editor ran");
+    assert!(!std::path::Path::new(opened.trim()).exists(), "{opened} was left behind");
+}
+
+#[test]
+fn branch_says_theres_nothing_to_commit_when_head_has_the_stash() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.write("a.txt", "a changed\n");
+    repo.git(["commit", "-q", "-am", "change a"]);
+    let script = repo.script(&["b", "q"]);
+
+    let output = repo.run(["--script", &script]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Stash produces no changes against current HEAD"), "{stderr}");
+    assert_eq!(repo.branches(), ["main"]);
+    assert_eq!(repo.stashes(), ["Change a"]);
+}
+
+#[test]
+fn dropping_the_first_stash_still_presents_the_other_two() {
+    let repo = TempRepo::new();
+    repo.stash("a.txt", "a\n", "a changed\n", "Change a");
+    repo.stash("b.txt", "b\n", "b changed\n", "Change b");
+    repo.stash("c.txt", "c\n", "c changed\n", "Change c");
+    let script = repo.script(&["d", "s", "s"]);
+
+    let output = repo.run(["--force", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // After the drop the rest move up, and neither is skipped.
+    let after_drop = &stdout[stdout.find("Dropped stash@{0}").expect("first stash dropped")..];
+    assert!(after_drop.contains("+b changed"), "{stdout}");
+    assert!(after_drop.contains("Stash 1 of 2"), "{stdout}");
+    assert!(after_drop.contains("Stash 2 of 2"), "{stdout}");
+    assert!(after_drop.contains("+a changed"), "{stdout}");
+    assert_eq!(repo.stashes(), ["Change b", "Change a"]);
+}
+
+#[test]
+fn a_failed_open_is_reported_and_the_session_goes_on() {
+    let repo = two_stashes();
+    let script = repo.script(&["o", "s", "s"]);
+
+    // There's nowhere to write the patch to open.
+    let output = repo.run_with_env("TMPDIR", "/nonexistent", ["--script", &script]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Couldn't write the patch to /nonexistent/"), "{stderr}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Stash 2 of 2"));
+    assert_eq!(repo.stashes(), ["newer", "older"]);
+}
+
+#[test]
+fn undo_brings_the_restored_stash_up_next() {
+    let repo = two_stashes();
+    repo.stash("c.txt", "c\n", "c changed\n", "newest");
+    let script = repo.script(&["s", "d", "u", "q"]);
+
+    let output = repo.run(["--force", "--script", &script]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // The skipped stash isn't gone over again.
+    let after_undo = &stdout[stdout.rfind("Stash 2 of 2").expect("drop prompt")..];
+    assert!(after_undo.contains("newer"), "{stdout}");
+    assert!(after_undo.contains("Stash 2 of 3"), "{stdout}");
+    assert!(!after_undo.contains("Stash 1 of 3"), "{stdout}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("skipped 1."), "{stderr}");
+    // git itself stores the restored stash back on top.
+    assert_eq!(repo.stashes(), ["newer", "newest", "older"]);
+}